@@ -0,0 +1,366 @@
+use crate::primitives::{Background, Command, PrimitiveList, Vertex};
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+const MAGIC: &[u8; 4] = b"PLST";
+const VERSION: u32 = 1;
+
+const TEXTURE_NONE: u8 = 0;
+const TEXTURE_NAMED: u8 = 1;
+const TEXTURE_UNNAMED: u8 = 2;
+
+/// Longest texture name that is read, to not allocate whatever a damaged
+/// file claims
+const MAX_NAME_LEN: u32 = 4096;
+
+/// What a capture holds, with textures by name so that it needs no device,
+/// e.g. for [`crate::raster::rasterize`]
+#[derive(Debug, Clone, Default)]
+pub struct Capture {
+    pub window_size: [u32; 2],
+    pub scale_factor: f32,
+    pub background: Background,
+    pub vtx: Vec<Vertex>,
+    pub idx: Vec<u32>,
+    pub commands: Vec<CapturedCommand>,
+}
+
+/// A [`Command`] with its texture by name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapturedCommand {
+    pub texture: CapturedTexture,
+    /// Scissor rect `[x1, y1, x2, y2]` in device pixels
+    pub clip: Option<[u32; 4]>,
+    pub idx_off: usize,
+    pub idx_cnt: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CapturedTexture {
+    #[default]
+    None,
+    Named(String),
+    /// A texture that had no name, which cannot be drawn again
+    Unnamed,
+}
+
+impl Capture {
+    /// Takes what was recorded into a primitive list, naming textures by
+    /// `textures`
+    pub fn new(list: &PrimitiveList, textures: &HashMap<String, wgpu::Texture>) -> Self {
+        let commands = list.commands.iter().map(|cmd| {
+            let texture = match cmd.texture {
+                None => CapturedTexture::None,
+                Some(ref tex) => match textures.iter().find(|(_, it)| *it == tex) {
+                    Some((name, _)) => CapturedTexture::Named(name.clone()),
+                    None => CapturedTexture::Unnamed,
+                },
+            };
+            CapturedCommand {
+                texture,
+                clip: cmd.clip,
+                idx_off: cmd.idx_off,
+                idx_cnt: cmd.idx_cnt,
+            }
+        });
+        Self {
+            window_size: list.window_size,
+            scale_factor: list.scale_factor,
+            background: list.background,
+            vtx: list.vtx.clone(),
+            idx: list.idx.clone(),
+            commands: commands.collect(),
+        }
+    }
+}
+
+/// Writes what was recorded into a primitive list, in a little-endian binary
+/// format. Textures are written by their name in `textures`, commands with
+/// others are left out when read back.
+pub fn write_primitives(
+    w: &mut impl Write,
+    list: &PrimitiveList,
+    textures: &HashMap<String, wgpu::Texture>,
+) -> io::Result<()> {
+    write_capture(w, &Capture::new(list, textures))
+}
+
+pub fn write_capture(w: &mut impl Write, capture: &Capture) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    write_u32(w, VERSION)?;
+    write_u32(w, capture.window_size[0])?;
+    write_u32(w, capture.window_size[1])?;
+    write_f32(w, capture.scale_factor)?;
+    match capture.background {
+        Background::Clear(color) => {
+            w.write_all(&[0])?;
+            color.iter().try_for_each(|&c| write_f32(w, c))?;
+        }
+        Background::Load => w.write_all(&[1])?,
+    }
+
+    write_u32(w, capture.vtx.len() as u32)?;
+    for v in &capture.vtx {
+        let floats = v.coord.iter().chain(&v.tex_coord).chain(&v.color);
+        floats.into_iter().try_for_each(|&x| write_f32(w, x))?;
+    }
+    write_u32(w, capture.idx.len() as u32)?;
+    capture.idx.iter().try_for_each(|&i| write_u32(w, i))?;
+
+    write_u32(w, capture.commands.len() as u32)?;
+    for cmd in &capture.commands {
+        match cmd.texture {
+            CapturedTexture::None => w.write_all(&[TEXTURE_NONE])?,
+            CapturedTexture::Unnamed => w.write_all(&[TEXTURE_UNNAMED])?,
+            CapturedTexture::Named(ref name) => {
+                w.write_all(&[TEXTURE_NAMED])?;
+                write_u32(w, name.len() as u32)?;
+                w.write_all(name.as_bytes())?;
+            }
+        }
+        match cmd.clip {
+            None => w.write_all(&[0])?,
+            Some(clip) => {
+                w.write_all(&[1])?;
+                clip.iter().try_for_each(|&x| write_u32(w, x))?;
+            }
+        }
+        write_u32(w, cmd.idx_off as u32)?;
+        write_u32(w, cmd.idx_cnt as u32)?;
+    }
+    Ok(())
+}
+
+/// Replaces the contents of `list` with what `write_primitives` wrote.
+/// Textures are looked up by name, commands whose texture is missing are
+/// left out. The captured window size is kept, so a replay into a window of
+/// another size is scaled to fit, and clip rects are clamped to the window
+/// drawn into like any others. On error the list is left as it was.
+pub fn read_primitives(
+    r: &mut impl Read,
+    list: &mut PrimitiveList,
+    textures: &HashMap<String, wgpu::Texture>,
+) -> io::Result<()> {
+    let capture = read_capture(r)?;
+    list.clear();
+    list.window_size = capture.window_size;
+    list.scale_factor = capture.scale_factor;
+    list.background = capture.background;
+    list.vtx = capture.vtx;
+    list.idx = capture.idx;
+    for cmd in capture.commands {
+        let texture = match cmd.texture {
+            CapturedTexture::None => None,
+            CapturedTexture::Named(name) => match textures.get(&name) {
+                Some(tex) => Some(tex.clone()),
+                None => continue,
+            },
+            CapturedTexture::Unnamed => continue,
+        };
+        list.commands.push(Command {
+            texture,
+            clip: cmd.clip,
+            idx_off: cmd.idx_off,
+            idx_cnt: cmd.idx_cnt,
+        });
+    }
+    Ok(())
+}
+
+/// Reads what `write_capture` or `write_primitives` wrote
+pub fn read_capture(r: &mut impl Read) -> io::Result<Capture> {
+    let mut capture = Capture::default();
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a primitive list capture"));
+    }
+    if read_u32(r)? != VERSION {
+        return Err(invalid_data("unsupported capture version"));
+    }
+    capture.window_size = [read_u32(r)?, read_u32(r)?];
+    capture.scale_factor = read_f32(r)?;
+    capture.background = match read_u8(r)? {
+        0 => Background::Clear([read_f32(r)?, read_f32(r)?, read_f32(r)?, read_f32(r)?]),
+        1 => Background::Load,
+        _ => return Err(invalid_data("unknown background")),
+    };
+
+    let count_vtx = read_u32(r)? as usize;
+    for _ in 0..count_vtx {
+        let mut floats = [0.0; 8];
+        for x in &mut floats {
+            *x = read_f32(r)?;
+        }
+        let [x, y, u, v, red, green, blue, alpha] = floats;
+        capture.vtx.push(Vertex {
+            coord: [x, y],
+            tex_coord: [u, v],
+            color: [red, green, blue, alpha],
+        });
+    }
+    let count_idx = read_u32(r)? as usize;
+    for _ in 0..count_idx {
+        let idx = read_u32(r)?;
+        if idx as usize >= count_vtx {
+            return Err(invalid_data("index out of range"));
+        }
+        capture.idx.push(idx);
+    }
+
+    let count_cmd = read_u32(r)?;
+    for _ in 0..count_cmd {
+        let texture = match read_u8(r)? {
+            TEXTURE_NONE => CapturedTexture::None,
+            TEXTURE_UNNAMED => CapturedTexture::Unnamed,
+            TEXTURE_NAMED => {
+                let len = read_u32(r)?;
+                if len > MAX_NAME_LEN {
+                    return Err(invalid_data("texture name too long"));
+                }
+                let mut name = Vec::new();
+                r.take(len as u64).read_to_end(&mut name)?;
+                if name.len() != len as usize {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                let name = String::from_utf8(name).map_err(|_| invalid_data("bad texture name"))?;
+                CapturedTexture::Named(name)
+            }
+            _ => return Err(invalid_data("unknown texture reference")),
+        };
+        let clip = match read_u8(r)? {
+            0 => None,
+            _ => Some([read_u32(r)?, read_u32(r)?, read_u32(r)?, read_u32(r)?]),
+        };
+        let idx_off = read_u32(r)? as usize;
+        let idx_cnt = read_u32(r)? as usize;
+        if idx_off + idx_cnt > count_idx {
+            return Err(invalid_data("command out of range"));
+        }
+        capture.commands.push(CapturedCommand {
+            texture,
+            clip,
+            idx_off,
+            idx_cnt,
+        });
+    }
+    Ok(capture)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u32(w: &mut impl Write, x: u32) -> io::Result<()> {
+    w.write_all(&x.to_le_bytes())
+}
+
+fn write_f32(w: &mut impl Write, x: f32) -> io::Result<()> {
+    w.write_all(&x.to_le_bytes())
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn round_trip() {
+        let mut list = PrimitiveList {
+            window_size: [640, 480],
+            scale_factor: 1.5,
+            ..Default::default()
+        };
+        list.color = [1.0, 0.5, 0.25, 1.0];
+        list.rect_f([-0.5, -0.5, 0.5, 0.5]);
+        list.clip_rect = Some([1, 2, 300, 400]);
+        list.circle_f([0.0, 0.0], 0.25);
+
+        let mut buf = Vec::new();
+        write_primitives(&mut buf, &list, &HashMap::new()).unwrap();
+        let mut loaded = PrimitiveList::default();
+        read_primitives(&mut buf.as_slice(), &mut loaded, &HashMap::new()).unwrap();
+
+        assert_eq!(loaded.window_size, list.window_size);
+        assert_eq!(loaded.scale_factor, list.scale_factor);
+        assert_eq!(loaded.background, list.background);
+        assert_eq!(loaded.idx, list.idx);
+        assert_eq!(loaded.vtx.len(), list.vtx.len());
+        for (a, b) in loaded.vtx.iter().zip(&list.vtx) {
+            assert_eq!(
+                (a.coord, a.tex_coord, a.color),
+                (b.coord, b.tex_coord, b.color)
+            );
+        }
+        assert_eq!(loaded.commands.len(), 2);
+        assert_eq!(loaded.commands[1].clip, list.commands[1].clip);
+        assert_eq!(loaded.commands[1].idx_off, list.commands[1].idx_off);
+
+        // A failed read leaves what was loaded before
+        buf.truncate(buf.len() - 1);
+        assert!(read_primitives(&mut buf.as_slice(), &mut loaded, &HashMap::new()).is_err());
+        assert_eq!(loaded.idx, list.idx);
+        assert_eq!(loaded.commands.len(), 2);
+    }
+
+    #[test]
+    fn bad_texture_names() {
+        let mut list = PrimitiveList::default();
+        list.rect_f([-0.5, -0.5, 0.5, 0.5]);
+        let mut buf = Vec::new();
+        write_primitives(&mut buf, &list, &HashMap::new()).unwrap();
+        // The only command comes last, written without a texture
+        let cmd_off = buf.len() - 10;
+        assert_eq!(buf[cmd_off], TEXTURE_NONE);
+
+        let with_name = |len: u32, name: &[u8]| {
+            let mut buf = buf.clone();
+            let tail = buf.split_off(cmd_off + 1);
+            buf[cmd_off] = TEXTURE_NAMED;
+            buf.extend(len.to_le_bytes());
+            buf.extend(name);
+            buf.extend(tail);
+            buf
+        };
+        let textures = HashMap::from([("font".to_string(), testing::texture([1, 1]))]);
+        let mut loaded = PrimitiveList::default();
+        let buf = with_name(4, b"font");
+        read_primitives(&mut buf.as_slice(), &mut loaded, &textures).unwrap();
+        assert!(loaded.commands[0].texture.is_some());
+        // A texture that is not there leaves out the command
+        let buf = with_name(4, b"nope");
+        read_primitives(&mut buf.as_slice(), &mut loaded, &textures).unwrap();
+        assert!(loaded.commands.is_empty());
+        let buf = with_name(4, b"font");
+        read_primitives(&mut buf.as_slice(), &mut loaded, &textures).unwrap();
+
+        // Claims far more than there is, without allocating it
+        let buf = with_name(u32::MAX, b"font");
+        let err = read_primitives(&mut buf.as_slice(), &mut loaded, &textures).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let buf = with_name(MAX_NAME_LEN, b"font");
+        let err = read_primitives(&mut buf.as_slice(), &mut loaded, &textures).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(loaded.commands[0].texture.is_some());
+    }
+}
@@ -13,6 +13,8 @@ pub struct Font {
     pub glyph_size: [u32; 2],
     pub glyphs: HashMap<char, [u32; 2]>,
     pub fallback_glyph: [u32; 2],
+    /// Opaque white, for solid fills that share the texture with text
+    pub white_texel: [u32; 2],
 }
 
 impl Font {
@@ -44,7 +46,8 @@ impl Font {
         // Currently we do not optimize texture size
         let texture_size = wgt::Extent3d {
             width: ceil_pow2(png_info.width).max(256),
-            height: ceil_pow2(png_info.height),
+            // leaves a row for the white texel
+            height: ceil_pow2(png_info.height + 1),
             depth_or_array_layers: 1,
         };
         let mut texture_data = vec![0; (4 * texture_size.width * texture_size.height) as usize];
@@ -56,6 +59,10 @@ impl Font {
             }
         }
 
+        let white_texel = [texture_size.width - 1, texture_size.height - 1];
+        let off_white = (white_texel[0] + texture_size.width * white_texel[1]) as usize * 4;
+        texture_data[off_white..][..4].fill(255);
+
         let texture = ctx.device.create_texture(&wgt::TextureDescriptor {
             label: Some("Default font texture"),
             size: texture_size,
@@ -88,6 +95,7 @@ impl Font {
             glyph_size: [12, 16],
             glyphs,
             fallback_glyph,
+            white_texel,
         }
     }
 }
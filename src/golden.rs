@@ -0,0 +1,179 @@
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::{offscreen::Offscreen, primitives::PrimitiveList, program::BlendSpace};
+
+/// Allowed difference per channel, as rasterizers may round differently
+pub const DEFAULT_TOLERANCE: u8 = 2;
+
+/// Mismatches of an image against its reference
+#[derive(Debug, Clone, Default)]
+pub struct Comparison {
+    pub mismatched: usize,
+    pub max_diff: u8,
+    /// Failing pixels in red over a dimmed copy of the actual image
+    pub diff: Vec<u8>,
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("golden")
+}
+
+/// Where the actual and diff images of failed comparisons go
+fn failure_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("golden")
+}
+
+/// Shared by all tests, `None` when there is no adapter to render with
+fn offscreen() -> Option<&'static Offscreen> {
+    static OFFSCREEN: OnceLock<Option<Offscreen>> = OnceLock::new();
+    OFFSCREEN
+        .get_or_init(|| pollster::block_on(Offscreen::new(BlendSpace::Srgb)))
+        .as_ref()
+}
+
+/// Renders the list and compares it with `golden/<name>.png`. On failure the
+/// actual and diff images are written to `target/golden/` before panicking.
+/// With `UPDATE_GOLDEN` set in the environment, the reference is written
+/// instead. Skipped if no adapter is available, which `UPDATE_GOLDEN` does
+/// not allow.
+pub fn assert_golden(name: &str, primitives: &PrimitiveList, tolerance: u8) {
+    let size = primitives.window_size;
+    let Some(offscreen) = offscreen() else {
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            panic!("no adapter available to update golden image {name}");
+        }
+        eprintln!("no adapter available, skipping golden image {name}");
+        return;
+    };
+    let actual = offscreen.render(primitives);
+    let golden_path = golden_dir().join(format!("{name}.png"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden_dir()).unwrap();
+        write_png(&golden_path, size, &actual).unwrap();
+        return;
+    }
+
+    let failure = match read_png(&golden_path) {
+        Ok((golden_size, _)) if golden_size != size => {
+            format!("size {size:?} does not match the reference {golden_size:?}")
+        }
+        Ok((_, expected)) => {
+            let cmp = compare_images(&expected, &actual, tolerance);
+            if cmp.mismatched == 0 {
+                return;
+            }
+            fs::create_dir_all(failure_dir()).unwrap();
+            let diff_path = failure_dir().join(format!("{name}.diff.png"));
+            write_png(&diff_path, size, &cmp.diff).unwrap();
+            format!(
+                "{} pixels differ by up to {}, see {}",
+                cmp.mismatched,
+                cmp.max_diff,
+                diff_path.display()
+            )
+        }
+        Err(err) => format!("cannot read {}: {err}", golden_path.display()),
+    };
+    fs::create_dir_all(failure_dir()).unwrap();
+    let actual_path = failure_dir().join(format!("{name}.actual.png"));
+    write_png(&actual_path, size, &actual).unwrap();
+    panic!(
+        "golden image {name}: {failure}, the actual image is in {}",
+        actual_path.display()
+    );
+}
+
+/// Compares RGBA images of the same size channel by channel
+pub fn compare_images(expected: &[u8], actual: &[u8], tolerance: u8) -> Comparison {
+    let mut cmp = Comparison::default();
+    for (e, a) in expected.chunks_exact(4).zip(actual.chunks_exact(4)) {
+        let diff = (0..4).map(|i| e[i].abs_diff(a[i])).max().unwrap();
+        cmp.max_diff = cmp.max_diff.max(diff);
+        if diff > tolerance {
+            cmp.mismatched += 1;
+            cmp.diff.extend([255, 0, 0, 255]);
+        } else {
+            cmp.diff.extend([a[0] / 4, a[1] / 4, a[2] / 4, 255]);
+        }
+    }
+    cmp
+}
+
+/// Reads an 8-bit RGBA PNG, as written by `write_png`
+pub fn read_png(path: &Path) -> io::Result<([u32; 2], Vec<u8>)> {
+    let decoder = png::Decoder::new(BufReader::new(fs::File::open(path)?));
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+    let info = reader.info();
+    if (info.color_type, info.bit_depth) != (png::ColorType::Rgba, png::BitDepth::Eight) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not 8-bit RGBA"));
+    }
+    let size = [info.width, info.height];
+    let mut data = vec![0; reader.output_buffer_size().unwrap()];
+    reader.next_frame(&mut data).map_err(io::Error::other)?;
+    Ok((size, data))
+}
+
+pub fn write_png(path: &Path, [width, height]: [u32; 2], data: &[u8]) -> io::Result<()> {
+    let file = BufWriter::new(fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(data).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{capture::Capture, primitives::Background, raster};
+    use std::collections::HashMap;
+
+    #[test]
+    fn compare_within_tolerance() {
+        let expected = [10, 20, 30, 255, 0, 0, 0, 255];
+        let actual = [12, 18, 30, 255, 0, 0, 9, 255];
+        let cmp = compare_images(&expected, &actual, 2);
+        assert_eq!(cmp.mismatched, 1);
+        assert_eq!(cmp.max_diff, 9);
+        assert_eq!(cmp.diff, [3, 4, 7, 255, 255, 0, 0, 255]);
+    }
+
+    fn basic_shapes() -> PrimitiveList {
+        let mut list = PrimitiveList {
+            window_size: [64, 64],
+            background: Background::Clear([0.125, 0.25, 0.5, 1.0]),
+            ..Default::default()
+        };
+        list.px_space = true;
+        list.color = [1.0, 0.0, 0.0, 1.0];
+        list.rect_f([8.0, 8.0, 40.0, 40.0]);
+        list.color = [0.0, 1.0, 0.0, 0.5];
+        list.rect_f([24.0, 24.0, 56.0, 56.0]);
+        list.clip_rect = Some([48, 0, 64, 16]);
+        list.color = [1.0; 4];
+        list.rect_f([40.0, 0.0, 64.0, 24.0]);
+        list
+    }
+
+    #[test]
+    fn golden_basic_shapes() {
+        assert_golden("basic_shapes", &basic_shapes(), DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn rasterizer_matches_golden() {
+        let (_, expected) = read_png(&golden_dir().join("basic_shapes.png")).unwrap();
+        let capture = Capture::new(&basic_shapes(), &HashMap::new());
+        let actual = raster::rasterize(&capture, BlendSpace::Srgb, &HashMap::new());
+        let cmp = compare_images(&expected, &actual, DEFAULT_TOLERANCE);
+        assert_eq!(cmp.mismatched, 0);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::primitives::Vertex;
+
+/// Deepest subdivision of a triangle for non-linear gradients
+const MAX_DEPTH: u32 = 6;
+/// Error of interpolating the gradient parameter linearly that is tolerated
+const MAX_PARAM_ERROR: f32 = 1.0 / 256.0;
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientKind {
+    /// Goes from 0 at `start` to 1 at `end`, constant across the direction
+    Linear { start: [f32; 2], end: [f32; 2] },
+    /// Goes from 0 at `center` to 1 at `radius` away from it, a radius that
+    /// is not positive paints the last stop
+    Radial { center: [f32; 2], radius: f32 },
+}
+
+/// Gradient paint, positions are in the coordinate space the shape is drawn in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    /// Offsets with sRGB colors, sorted by offset. Colors are padded with
+    /// the first and last stop outside of their range.
+    pub stops: Vec<(f32, [f32; 4])>,
+}
+
+#[allow(unused)]
+impl Gradient {
+    pub fn linear(start: [f32; 2], end: [f32; 2], stops: &[(f32, [f32; 4])]) -> Self {
+        Self::new(GradientKind::Linear { start, end }, stops)
+    }
+
+    pub fn radial(center: [f32; 2], radius: f32, stops: &[(f32, [f32; 4])]) -> Self {
+        Self::new(GradientKind::Radial { center, radius }, stops)
+    }
+
+    fn new(kind: GradientKind, stops: &[(f32, [f32; 4])]) -> Self {
+        let mut stops = stops.to_vec();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { kind, stops }
+    }
+
+    pub fn color_at(&self, t: f32) -> [f32; 4] {
+        self.color_beside(t, false)
+    }
+
+    /// Color just above `t` when `above` is set, or just below it. Differs
+    /// from [`Self::color_at`] only at hard stops, where two stops share
+    /// an offset.
+    fn color_beside(&self, t: f32, above: bool) -> [f32; 4] {
+        let Some(&(first_off, first_color)) = self.stops.first() else {
+            return [1.0; 4];
+        };
+        if t < first_off || (t == first_off && !above) {
+            return first_color;
+        }
+        for pair in self.stops.windows(2) {
+            let [(off1, color1), (off2, color2)] = [pair[0], pair[1]];
+            if t < off2 || (t == off2 && !above) {
+                let k = if off2 > off1 {
+                    (t - off1) / (off2 - off1)
+                } else {
+                    1.0
+                };
+                return [0, 1, 2, 3].map(|i| color1[i] + (color2[i] - color1[i]) * k);
+            }
+        }
+        self.stops.last().unwrap().1
+    }
+
+    pub fn param_at(&self, [x, y]: [f32; 2]) -> f32 {
+        match self.kind {
+            GradientKind::Linear { start, end } => {
+                let [dx, dy] = [end[0] - start[0], end[1] - start[1]];
+                let len2 = dx * dx + dy * dy;
+                if len2 > 0.0 {
+                    ((x - start[0]) * dx + (y - start[1]) * dy) / len2
+                } else {
+                    0.0
+                }
+            }
+            GradientKind::Radial { center, radius } => {
+                if radius.is_nan() || radius <= 0.0 {
+                    // Nothing is inside, everything gets the last stop
+                    return self.stops.last().map_or(1.0, |it| it.0);
+                }
+                let [dx, dy] = [x - center[0], y - center[1]];
+                (dx * dx + dy * dy).sqrt() / radius
+            }
+        }
+    }
+
+    /// Multiplies the colors of a triangle by the gradient. The triangle is
+    /// split where needed, so that per-vertex interpolation shows the stops
+    /// in the right places. `to_user` maps vertex coordinates back to the
+    /// space of the gradient, the resulting triangles are appended to `out`.
+    pub fn paint_triangle(
+        &self,
+        tri: [Vertex; 3],
+        to_user: &impl Fn([f32; 2]) -> [f32; 2],
+        out: &mut Vec<Vertex>,
+    ) {
+        let params = tri.map(|v| self.param_at(to_user(v.coord)));
+        match self.kind {
+            GradientKind::Linear { .. } => self.split_by_stops(tri, params, out),
+            GradientKind::Radial { .. } => self.subdivide(tri, params, to_user, 0, out),
+        }
+    }
+
+    /// Splits until the parameter is close to linear across each triangle
+    fn subdivide(
+        &self,
+        tri: [Vertex; 3],
+        params: [f32; 3],
+        to_user: &impl Fn([f32; 2]) -> [f32; 2],
+        depth: u32,
+        out: &mut Vec<Vertex>,
+    ) {
+        let mids = [(0, 1), (1, 2), (2, 0)].map(|(i, j)| lerp_vertex(tri[i], tri[j], 0.5));
+        let mid_params = mids.map(|v| self.param_at(to_user(v.coord)));
+        let error = [(0, 1), (1, 2), (2, 0)]
+            .into_iter()
+            .enumerate()
+            .map(|(k, (i, j))| (mid_params[k] - 0.5 * (params[i] + params[j])).abs())
+            .fold(0.0, f32::max);
+        if depth >= MAX_DEPTH || error <= MAX_PARAM_ERROR {
+            self.split_by_stops(tri, params, out);
+            return;
+        }
+        let [m01, m12, m20] = mids;
+        let [p01, p12, p20] = mid_params;
+        let [v0, v1, v2] = tri;
+        let [q0, q1, q2] = params;
+        for (tri, params) in [
+            ([v0, m01, m20], [q0, p01, p20]),
+            ([m01, v1, m12], [p01, q1, p12]),
+            ([m20, m12, v2], [p20, p12, q2]),
+            ([m01, m12, m20], [p01, p12, p20]),
+        ] {
+            self.subdivide(tri, params, to_user, depth + 1, out);
+        }
+    }
+
+    /// Cuts a triangle, across which the parameter is linear, along the stops
+    fn split_by_stops(&self, tri: [Vertex; 3], params: [f32; 3], out: &mut Vec<Vertex>) {
+        let lo = params.into_iter().fold(f32::INFINITY, f32::min);
+        let hi = params.into_iter().fold(-f32::INFINITY, f32::max);
+        let mut cuts = vec![-f32::INFINITY];
+        cuts.extend(self.stops.iter().map(|s| s.0).filter(|&t| lo < t && t < hi));
+        cuts.push(f32::INFINITY);
+
+        let poly: Vec<_> = tri.into_iter().zip(params).collect();
+        for range in cuts.windows(2) {
+            let slice = clip(&poly, range[0], 1.0);
+            let slice = clip(&slice, range[1], -1.0);
+            if slice.len() < 3 {
+                continue;
+            }
+            // Vertices on the lower end of the slice take the color above
+            // them, so that hard stops do not bleed into the next slice
+            let mid = slice.iter().map(|it| it.1).sum::<f32>() / slice.len() as f32;
+            let painted: Vec<_> = slice
+                .into_iter()
+                .map(|(mut v, t)| {
+                    let paint = self.color_beside(t, t < mid);
+                    v.color = [0, 1, 2, 3].map(|i| v.color[i] * paint[i]);
+                    v
+                })
+                .collect();
+            for i in 2..painted.len() {
+                out.extend([painted[0], painted[i - 1], painted[i]]);
+            }
+        }
+    }
+}
+
+/// Keeps the part of a convex polygon where `(t - bound) * sign >= 0`
+fn clip(poly: &[(Vertex, f32)], bound: f32, sign: f32) -> Vec<(Vertex, f32)> {
+    if bound.is_infinite() {
+        return poly.to_vec();
+    }
+    let inside = |t: f32| (t - bound) * sign >= 0.0;
+    let mut result = Vec::with_capacity(poly.len() + 1);
+    for (i, &(v1, t1)) in poly.iter().enumerate() {
+        let (v2, t2) = poly[(i + 1) % poly.len()];
+        if inside(t1) {
+            result.push((v1, t1));
+        }
+        if inside(t1) != inside(t2) {
+            let k = (bound - t1) / (t2 - t1);
+            result.push((lerp_vertex(v1, v2, k), bound));
+        }
+    }
+    result
+}
+
+fn lerp_vertex(v1: Vertex, v2: Vertex, k: f32) -> Vertex {
+    let lerp = |a: f32, b: f32| a + (b - a) * k;
+    Vertex {
+        coord: [0, 1].map(|i| lerp(v1.coord[i], v2.coord[i])),
+        tex_coord: [0, 1].map(|i| lerp(v1.tex_coord[i], v2.tex_coord[i])),
+        color: [0, 1, 2, 3].map(|i| lerp(v1.color[i], v2.color[i])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+    const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+    const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+    fn assert_color(actual: [f32; 4], expected: [f32; 4]) {
+        let close = (0..4).all(|i| (actual[i] - expected[i]).abs() < 1e-4);
+        assert!(close, "expected {expected:?}, got {actual:?}");
+    }
+
+    fn white(coord: [f32; 2]) -> Vertex {
+        Vertex {
+            coord,
+            tex_coord: [0.0; 2],
+            color: [1.0; 4],
+        }
+    }
+
+    /// Paints a triangle with the identity as `to_user`
+    fn paint(gradient: &Gradient, tri: [[f32; 2]; 3]) -> Vec<Vertex> {
+        let mut out = Vec::new();
+        gradient.paint_triangle(tri.map(white), &|p| p, &mut out);
+        out
+    }
+
+    #[test]
+    fn colors_at_stops() {
+        let gradient = Gradient::linear(
+            [0.0, 0.0],
+            [1.0, 0.0],
+            &[(1.0, BLUE), (0.0, RED), (0.25, GREEN)],
+        );
+        assert_color(gradient.color_at(0.0), RED);
+        assert_color(gradient.color_at(0.25), GREEN);
+        assert_color(gradient.color_at(1.0), BLUE);
+        assert_color(gradient.color_at(0.125), [0.5, 0.5, 0.0, 1.0]);
+        // Padded outside of the stops
+        assert_color(gradient.color_at(-1.0), RED);
+        assert_color(gradient.color_at(2.0), BLUE);
+    }
+
+    #[test]
+    fn hard_stop() {
+        let stops = [(0.0, RED), (0.5, RED), (0.5, BLUE), (1.0, BLUE)];
+        let gradient = Gradient::linear([0.0, 0.0], [1.0, 0.0], &stops);
+        assert_color(gradient.color_at(0.49), RED);
+        assert_color(gradient.color_at(0.51), BLUE);
+
+        // The triangle is cut at the stop, so no triangle blends across it
+        let out = paint(&gradient, [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        assert!(out.len() > 3);
+        for tri in out.chunks_exact(3) {
+            let left = tri.iter().all(|v| v.coord[0] <= 0.5 + 1e-6);
+            let right = tri.iter().all(|v| v.coord[0] >= 0.5 - 1e-6);
+            assert!(left || right, "{tri:?}");
+            let expected = if left { RED } else { BLUE };
+            let centroid = [0, 1].map(|i| tri.iter().map(|v| v.coord[i]).sum::<f32>() / 3.0);
+            if (centroid[0] - 0.5).abs() > 1e-3 {
+                tri.iter().for_each(|v| assert_color(v.color, expected));
+            }
+        }
+    }
+
+    #[test]
+    fn linear_vertices_match_color_at() {
+        let stops = [(0.0, RED), (0.3, GREEN), (1.0, BLUE)];
+        let gradient = Gradient::linear([0.0, 0.0], [2.0, 0.0], &stops);
+        let out = paint(&gradient, [[0.0, 0.0], [2.0, 0.0], [1.0, 1.0]]);
+        for v in &out {
+            assert_color(v.color, gradient.color_at(gradient.param_at(v.coord)));
+        }
+    }
+
+    #[test]
+    fn radial_center_and_radius() {
+        let gradient = Gradient::radial([1.0, 1.0], 2.0, &[(0.0, RED), (1.0, BLUE)]);
+        assert_color(gradient.color_at(gradient.param_at([1.0, 1.0])), RED);
+        assert_color(gradient.color_at(gradient.param_at([3.0, 1.0])), BLUE);
+        assert_color(
+            gradient.color_at(gradient.param_at([1.0, 2.0])),
+            [0.5, 0.0, 0.5, 1.0],
+        );
+
+        // Subdivided until the vertex colors follow the distance closely
+        let out = paint(&gradient, [[1.0, 1.0], [3.0, 1.0], [1.0, 3.0]]);
+        assert!(out.len() > 3);
+        for v in &out {
+            assert_color(v.color, gradient.color_at(gradient.param_at(v.coord)));
+        }
+    }
+
+    #[test]
+    fn radial_zero_radius() {
+        let gradient = Gradient::radial([0.0, 0.0], 0.0, &[(0.0, RED), (1.0, BLUE)]);
+        assert_color(gradient.color_at(gradient.param_at([0.0, 0.0])), BLUE);
+        let out = paint(&gradient, [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        assert!(!out.is_empty());
+        for v in &out {
+            assert!(v.coord.iter().chain(&v.color).all(|it| it.is_finite()));
+            assert_color(v.color, BLUE);
+        }
+    }
+}
@@ -1,8 +1,17 @@
+mod capture;
 mod font;
+#[cfg(test)]
+mod golden;
+mod gradient;
+mod offscreen;
+mod path;
 mod primitives;
 mod program;
+mod raster;
+#[cfg(test)]
+mod testing;
 
-use crate::primitives::PrimitiveList;
+use crate::primitives::{Background, Shadow, StrokeAlign};
 use sdl3::{
     event::{Event, WindowEvent},
     keyboard::Scancode,
@@ -10,9 +19,27 @@ use sdl3::{
 };
 
 fn main() {
-    let mut program_ctx = pollster::block_on(program::Context::new());
-    let mut primitives = PrimitiveList::default();
+    let config = program::ContextConfig {
+        pipeline_cache_dir: Some(std::env::temp_dir().join("sandbox-wgpu")),
+        ..Default::default()
+    };
+    let mut program_ctx = pollster::block_on(program::Context::new(config));
     let font = font::Font::new(&program_ctx);
+    program_ctx
+        .textures
+        .insert("font".to_string(), font.texture.clone());
+    program_ctx.batch_texture = Some((font.texture.clone(), font.white_texel));
+    let main_window = program_ctx.main_window;
+    let mut inspector_window = None;
+    let mut show_stats = false;
+    // Draws a saved capture instead of the demo
+    let replay = std::env::args().skip_while(|it| it != "--replay").nth(1);
+    if let Some(ref path) = replay
+        && let Err(err) = program_ctx.load_primitives(main_window, path.as_ref())
+    {
+        eprintln!("Failed to load capture {path}: {err}");
+        std::process::exit(1);
+    }
 
     const WINDOW_PADDING: f32 = 8.0;
     const GRID_STEP: f32 = 8.0;
@@ -26,10 +53,24 @@ fn main() {
             match event {
                 Event::Quit { .. } => break 'main_loop,
                 Event::Window {
-                    win_event: WindowEvent::Resized(_, _),
+                    window_id,
+                    win_event: WindowEvent::Resized(_, _) | WindowEvent::PixelSizeChanged(_, _),
+                    ..
+                } => {
+                    program_ctx.on_resize(window_id);
+                }
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::CloseRequested,
                     ..
                 } => {
-                    program_ctx.on_resize();
+                    if window_id == main_window {
+                        break 'main_loop;
+                    }
+                    program_ctx.close_window(window_id);
+                    if inspector_window == Some(window_id) {
+                        inspector_window = None;
+                    }
                 }
                 Event::KeyDown {
                     scancode: Some(scancode),
@@ -37,6 +78,21 @@ fn main() {
                 } => match scancode {
                     Scancode::Escape => break 'main_loop,
                     Scancode::LCtrl => ctrl_pressed = true,
+                    Scancode::F3 => show_stats = !show_stats,
+                    Scancode::F12 => {
+                        let path = std::env::temp_dir().join("sandbox-wgpu.plst");
+                        match program_ctx.save_primitives(main_window, &path) {
+                            Ok(()) => println!("Saved capture to {}", path.display()),
+                            Err(err) => eprintln!("Failed to save capture: {err}"),
+                        }
+                    }
+                    Scancode::F2 => match inspector_window.take() {
+                        Some(window_id) => program_ctx.close_window(window_id),
+                        None => {
+                            let window_id = program_ctx.create_window("Inspector", 320, 240);
+                            inspector_window = Some(window_id);
+                        }
+                    },
                     _ => {}
                 },
                 Event::KeyUp {
@@ -44,12 +100,13 @@ fn main() {
                     ..
                 } => ctrl_pressed = false,
                 Event::MouseButtonDown {
+                    window_id,
                     mouse_btn: MouseButton::Left,
                     clicks: 1,
                     x,
                     y,
                     ..
-                } => {
+                } if window_id == main_window => {
                     let mut mask = 0;
                     if window_pos[0] <= x && x <= window_pos[0] + WINDOW_PADDING {
                         mask |= 1;
@@ -66,12 +123,13 @@ fn main() {
                     }
                 }
                 Event::MouseButtonUp {
+                    window_id,
                     mouse_btn: MouseButton::Left,
                     clicks: 1,
                     x,
                     y,
                     ..
-                } => {
+                } if window_id == main_window => {
                     if let Some((x_og, y_og, mask)) = window_drag {
                         let delta = [x - x_og, y - y_og];
                         for i in 0..4 {
@@ -84,13 +142,16 @@ fn main() {
                         }
                         window_pos[0] = window_pos[0].max(0.0);
                         window_pos[1] = window_pos[1].max(0.0);
-                        window_pos[2] = window_pos[2].min(program_ctx.surface_config.width as f32);
-                        window_pos[3] = window_pos[3].min(program_ctx.surface_config.height as f32);
+                        let (width, height) = program_ctx.main_window().window.size();
+                        window_pos[2] = window_pos[2].min(width as f32);
+                        window_pos[3] = window_pos[3].min(height as f32);
                         window_pos_og = window_pos;
                         window_drag = None;
                     }
                 }
-                Event::MouseMotion { x, y, .. } => {
+                Event::MouseMotion {
+                    window_id, x, y, ..
+                } if window_id == main_window => {
                     if let Some((x_og, y_og, mask)) = window_drag {
                         let delta = [x - x_og, y - y_og];
                         for i in 0..4 {
@@ -103,49 +164,78 @@ fn main() {
                         }
                         window_pos[0] = window_pos[0].max(0.0);
                         window_pos[1] = window_pos[1].max(0.0);
-                        window_pos[2] = window_pos[2].min(program_ctx.surface_config.width as f32);
-                        window_pos[3] = window_pos[3].min(program_ctx.surface_config.height as f32);
+                        let (width, height) = program_ctx.main_window().window.size();
+                        window_pos[2] = window_pos[2].min(width as f32);
+                        window_pos[3] = window_pos[3].min(height as f32);
                     }
                 }
                 _ => {}
             }
         }
 
-        primitives.clear();
-        primitives.window_size = [
-            program_ctx.surface_config.width,
-            program_ctx.surface_config.height,
-        ];
+        if replay.is_none() {
+            let stats = program_ctx.main_window().stats;
+            let primitives = program_ctx.main_window_mut().begin_frame();
+            primitives.logical_px = true;
 
-        primitives.texture = None;
-        primitives.px_space = true;
-        primitives.color = [1.0; 4];
-        primitives.rect_f(window_pos);
-        primitives.color = [0.75, 0.75, 0.75, 1.0];
-        primitives.rect_f([
-            window_pos[0] + WINDOW_PADDING,
-            window_pos[1] + WINDOW_PADDING,
-            window_pos[2] - WINDOW_PADDING,
-            window_pos[3] - WINDOW_PADDING,
-        ]);
+            primitives.texture = None;
+            primitives.px_space = true;
+            primitives.shadow_rect_f(
+                window_pos,
+                [0.0; 4],
+                &Shadow {
+                    offset: [0.0, 4.0],
+                    blur: 16.0,
+                    spread: 0.0,
+                    color: [0.0, 0.0, 0.0, 0.5],
+                },
+            );
+            primitives.color = [0.75, 0.75, 0.75, 1.0];
+            primitives.rect_f(window_pos);
+            primitives.color = [1.0; 4];
+            primitives.stroke.width = WINDOW_PADDING;
+            primitives.stroke.align = StrokeAlign::Inner;
+            primitives.rect_outline_f(window_pos);
 
-        primitives.texture = Some(font.texture.clone());
-        primitives.color = [0.0, 0.0, 0.0, 1.0];
-        primitives.text_i(
-            &font,
-            [
-                (window_pos[0] + WINDOW_PADDING) as i32,
-                (window_pos[1] + WINDOW_PADDING) as i32,
-            ],
-            "Hello, world!",
-        );
+            primitives.texture = Some(font.texture.clone());
+            primitives.color = [0.0, 0.0, 0.0, 1.0];
+            primitives.text_i(
+                &font,
+                [
+                    (window_pos[0] + WINDOW_PADDING) as i32,
+                    (window_pos[1] + WINDOW_PADDING) as i32,
+                ],
+                "Hello, world!",
+            );
+            if show_stats {
+                stats.draw_overlay(primitives, &font, [8, 8]);
+            }
+        }
+
+        if let Some(window_id) = inspector_window {
+            let primitives = program_ctx
+                .windows
+                .get_mut(&window_id)
+                .unwrap()
+                .begin_frame();
+            primitives.background = Background::Clear([0.125, 0.125, 0.125, 1.0]);
+            primitives.logical_px = true;
+            primitives.color = [1.0; 4];
+            let text = format!(
+                "Window\n{:.0} {:.0}\n{:.0} {:.0}",
+                window_pos[0], window_pos[1], window_pos[2], window_pos[3],
+            );
+            primitives.text_i(&font, [8, 8], &text);
+        }
 
-        match program_ctx.on_frame(&primitives) {
-            Ok(()) => {}
-            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
-                program_ctx.on_resize();
+        for window_id in [Some(main_window), inspector_window].into_iter().flatten() {
+            match program_ctx.on_frame(window_id) {
+                Ok(()) => {}
+                Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                    program_ctx.on_resize(window_id);
+                }
+                Err(err) => panic!("{err}"),
             }
-            Err(err) => panic!("{err}"),
         }
     }
     program_ctx
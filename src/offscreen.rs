@@ -0,0 +1,241 @@
+use std::mem;
+use wgpu::{util::DeviceExt, wgt};
+
+use crate::{
+    primitives::{Background, PrimitiveList},
+    program::{self, BlendSpace},
+    raster::Image,
+};
+
+/// Renders primitive lists into textures that are read back, without any
+/// window. Gets its own device, so it works where no display is available.
+#[allow(unused)]
+pub struct Offscreen {
+    pub format: wgt::TextureFormat,
+    pub pipeline: wgpu::RenderPipeline,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub white_tex_view: wgpu::TextureView,
+    pub default_sampler: wgpu::Sampler,
+    pub queue: wgpu::Queue,
+    pub device: wgpu::Device,
+    pub adapter: wgpu::Adapter,
+}
+
+#[allow(unused)]
+impl Offscreen {
+    /// Prefers the fallback adapter, i.e. a software renderer, so that the
+    /// results do not depend on the GPU. Returns `None` without any adapter.
+    pub async fn new(blend_space: BlendSpace) -> Option<Self> {
+        let instance = wgpu::Instance::new(&wgt::InstanceDescriptor::from_env_or_default());
+        let mut adapter = None;
+        for force_fallback_adapter in [true, false] {
+            let options = wgt::RequestAdapterOptions {
+                power_preference: wgt::PowerPreference::LowPower,
+                force_fallback_adapter,
+                compatible_surface: None,
+            };
+            adapter = instance.request_adapter(&options).await.ok();
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter?;
+        let (device, queue) = adapter.request_device(&Default::default()).await.ok()?;
+
+        let format = match blend_space {
+            BlendSpace::Srgb => wgt::TextureFormat::Rgba8Unorm,
+            BlendSpace::Linear => wgt::TextureFormat::Rgba8UnormSrgb,
+        };
+        let shader = device.create_shader_module(wgpu::include_wgsl!("primitives.wgsl"));
+        let bind_group_layout = program::create_primitive_bind_group_layout(&device);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Offscreen pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline =
+            program::create_primitive_pipeline(&device, &pipeline_layout, &shader, None, format);
+        let white_tex = program::create_white_texture(&device, &queue);
+        let white_tex_view = white_tex.create_view(&Default::default());
+        let default_sampler = device.create_sampler(&Default::default());
+
+        Some(Self {
+            format,
+            pipeline,
+            bind_group_layout,
+            white_tex_view,
+            default_sampler,
+            queue,
+            device,
+            adapter,
+        })
+    }
+
+    /// Uploads an image for primitive lists rendered with this device
+    pub fn create_texture(&self, image: &Image) -> wgpu::Texture {
+        let [width, height] = image.size;
+        let size = wgt::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgt::TextureDescriptor {
+            label: Some("Offscreen image texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgt::TextureDimension::D2,
+            format: wgt::TextureFormat::Rgba8Unorm,
+            usage: wgt::TextureUsages::COPY_DST | wgt::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let layout = wgt::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: None,
+        };
+        let dst = texture.as_image_copy();
+        self.queue.write_texture(dst, &image.data, layout, size);
+        texture
+    }
+
+    /// Draws the list at its `window_size` and returns the pixels as RGBA
+    /// rows, top to bottom. Textures must belong to this device.
+    pub fn render(&self, primitives: &PrimitiveList) -> Vec<u8> {
+        let [width, height] = primitives.window_size.map(|it| it.max(1));
+        let size = wgt::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let target = self.device.create_texture(&wgt::TextureDescriptor {
+            label: Some("Offscreen target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgt::TextureDimension::D2,
+            format: self.format,
+            usage: wgt::TextureUsages::RENDER_ATTACHMENT | wgt::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let target_view = target.create_view(&Default::default());
+        let load = match primitives.background {
+            Background::Clear(color) => {
+                wgt::LoadOp::Clear(program::clear_color(color, self.format))
+            }
+            // Nothing was drawn before, so there is nothing to keep
+            Background::Load => wgt::LoadOp::Clear(wgt::Color::TRANSPARENT),
+        };
+
+        let mut vtx_data = Vec::with_capacity(primitives.vtx.len() * 32);
+        for v in &primitives.vtx {
+            for x in v.coord.iter().chain(&v.tex_coord).chain(&v.color) {
+                vtx_data.extend_from_slice(&x.to_ne_bytes());
+            }
+        }
+        let idx_data: Vec<u8> = primitives
+            .idx
+            .iter()
+            .flat_map(|i| i.to_ne_bytes())
+            .collect();
+        // Zero-sized buffers cannot be bound
+        vtx_data.resize(vtx_data.len().max(4), 0);
+        let idx_data = if idx_data.is_empty() {
+            vec![0; 4]
+        } else {
+            idx_data
+        };
+        let vtx_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Offscreen vertex buffer"),
+                contents: &vtx_data,
+                usage: wgt::BufferUsages::VERTEX,
+            });
+        let idx_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Offscreen index buffer"),
+                contents: &idx_data,
+                usage: wgt::BufferUsages::INDEX,
+            });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Offscreen render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target_view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgt::Operations {
+                    load,
+                    store: wgt::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_index_buffer(idx_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.set_vertex_buffer(0, vtx_buffer.slice(..));
+        for cmd in &primitives.commands {
+            let texture_view = match cmd.texture {
+                None => &self.white_tex_view,
+                Some(ref tex) => &tex.create_view(&Default::default()),
+            };
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(texture_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.default_sampler),
+                    },
+                ],
+            });
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            let [x, y, w, h] = program::scissor_rect(cmd.clip, [width, height]);
+            render_pass.set_scissor_rect(x, y, w, h);
+            let idx_end = cmd.idx_off + cmd.idx_cnt;
+            render_pass.draw_indexed(cmd.idx_off as u32..idx_end as u32, 0, 0..1);
+        }
+        mem::drop(render_pass);
+
+        // Rows of the copy must be aligned, the padding is stripped below
+        let row_size = 4 * width;
+        let padded_row_size = row_size.next_multiple_of(wgt::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let readback = self.device.create_buffer(&wgt::BufferDescriptor {
+            label: Some("Offscreen readback buffer"),
+            size: (padded_row_size * height) as u64,
+            usage: wgt::BufferUsages::COPY_DST | wgt::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            target.as_image_copy(),
+            wgt::TexelCopyBufferInfo {
+                buffer: &readback,
+                layout: wgt::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        self.queue.submit([encoder.finish()]);
+        readback.map_async(wgpu::MapMode::Read, .., Result::unwrap);
+        self.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .unwrap();
+
+        let mapping = readback.get_mapped_range(..);
+        let mut pixels = Vec::with_capacity((row_size * height) as usize);
+        for row in mapping.chunks(padded_row_size as usize) {
+            pixels.extend_from_slice(&row[..row_size as usize]);
+        }
+        pixels
+    }
+}
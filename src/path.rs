@@ -0,0 +1,699 @@
+const MAX_CURVE_SEGMENTS: usize = 1024;
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FillRule {
+    #[default]
+    NonZero,
+    EvenOdd,
+}
+
+impl FillRule {
+    fn is_inside(self, winding: i32) -> bool {
+        match self {
+            Self::NonZero => winding != 0,
+            Self::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Segment {
+    MoveTo([f32; 2]),
+    LineTo([f32; 2]),
+    QuadTo([f32; 2], [f32; 2]),
+    CubicTo([f32; 2], [f32; 2], [f32; 2]),
+    Close,
+}
+
+/// Outline made of subpaths, in the coordinate space it is drawn in
+#[derive(Debug, Clone, Default)]
+pub struct Path {
+    segments: Vec<Segment>,
+}
+
+/// Polyline approximation of a subpath
+#[derive(Debug, Clone, Default)]
+pub struct Contour {
+    pub points: Vec<[f32; 2]>,
+    pub closed: bool,
+}
+
+/// Part of a filled area between two edges, with horizontal top and bottom
+#[derive(Debug, Clone, Copy)]
+pub struct Trapezoid {
+    pub y: [f32; 2],
+    /// X coordinates of the left edge at the top and bottom
+    pub left: [f32; 2],
+    /// X coordinates of the right edge at the top and bottom
+    pub right: [f32; 2],
+}
+
+#[allow(unused)]
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    pub fn move_to(&mut self, p: [f32; 2]) {
+        self.segments.push(Segment::MoveTo(p));
+    }
+
+    pub fn line_to(&mut self, p: [f32; 2]) {
+        self.segments.push(Segment::LineTo(p));
+    }
+
+    pub fn quad_to(&mut self, ctrl: [f32; 2], p: [f32; 2]) {
+        self.segments.push(Segment::QuadTo(ctrl, p));
+    }
+
+    pub fn cubic_to(&mut self, ctrl1: [f32; 2], ctrl2: [f32; 2], p: [f32; 2]) {
+        self.segments.push(Segment::CubicTo(ctrl1, ctrl2, p));
+    }
+
+    /// Closes the current subpath, the next one starts at its first point
+    pub fn close(&mut self) {
+        self.segments.push(Segment::Close);
+    }
+
+    /// Approximates curves by line segments that stay within `tolerance`
+    pub fn flatten(&self, tolerance: f32) -> Vec<Contour> {
+        let mut contours = Vec::new();
+        let mut curr = Contour::default();
+        let mut start = [0.0; 2];
+        for &segment in &self.segments {
+            let last = curr.points.last().copied().unwrap_or(start);
+            match segment {
+                Segment::MoveTo(p) => {
+                    finish_contour(&mut contours, &mut curr);
+                    start = p;
+                    curr.points.push(p);
+                }
+                Segment::LineTo(p) => {
+                    if curr.points.is_empty() {
+                        curr.points.push(last);
+                    }
+                    curr.points.push(p);
+                }
+                Segment::QuadTo(ctrl, p) => {
+                    if curr.points.is_empty() {
+                        curr.points.push(last);
+                    }
+                    // The second derivative is constant, so a uniform split
+                    // with n segments deviates by at most |B''| / (8 n^2)
+                    let dd = length(second_diff(last, ctrl, p));
+                    let n = curve_segments(dd / 4.0, tolerance);
+                    for i in 1..=n {
+                        let t = i as f32 / n as f32;
+                        curr.points.push(quad_point(last, ctrl, p, t));
+                    }
+                }
+                Segment::CubicTo(ctrl1, ctrl2, p) => {
+                    if curr.points.is_empty() {
+                        curr.points.push(last);
+                    }
+                    // Wang's formula, |B''| is at most 6 times the largest second difference
+                    let dd1 = length(second_diff(last, ctrl1, ctrl2));
+                    let dd2 = length(second_diff(ctrl1, ctrl2, p));
+                    let n = curve_segments(0.75 * dd1.max(dd2), tolerance);
+                    for i in 1..=n {
+                        let t = i as f32 / n as f32;
+                        curr.points.push(cubic_point(last, ctrl1, ctrl2, p, t));
+                    }
+                }
+                Segment::Close => {
+                    curr.closed = true;
+                    finish_contour(&mut contours, &mut curr);
+                }
+            }
+        }
+        finish_contour(&mut contours, &mut curr);
+        contours
+    }
+}
+
+fn finish_contour(contours: &mut Vec<Contour>, curr: &mut Contour) {
+    let contour = std::mem::take(curr);
+    if contour.points.len() > 1 {
+        contours.push(contour);
+    }
+}
+
+/// Segments needed for a deviation of `k / n^2` to stay within `tolerance`
+fn curve_segments(k: f32, tolerance: f32) -> usize {
+    let n = (k / tolerance).sqrt().ceil();
+    if n.is_finite() {
+        (n as usize).clamp(1, MAX_CURVE_SEGMENTS)
+    } else {
+        MAX_CURVE_SEGMENTS
+    }
+}
+
+fn second_diff(p0: [f32; 2], p1: [f32; 2], p2: [f32; 2]) -> [f32; 2] {
+    [0, 1].map(|i| p0[i] - 2.0 * p1[i] + p2[i])
+}
+
+fn length([x, y]: [f32; 2]) -> f32 {
+    (x * x + y * y).sqrt()
+}
+
+fn quad_point(p0: [f32; 2], p1: [f32; 2], p2: [f32; 2], t: f32) -> [f32; 2] {
+    let s = 1.0 - t;
+    let [k0, k1, k2] = [s * s, 2.0 * s * t, t * t];
+    [0, 1].map(|i| k0 * p0[i] + k1 * p1[i] + k2 * p2[i])
+}
+
+fn cubic_point(p0: [f32; 2], p1: [f32; 2], p2: [f32; 2], p3: [f32; 2], t: f32) -> [f32; 2] {
+    let s = 1.0 - t;
+    let [k0, k1, k2, k3] = [s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t];
+    [0, 1].map(|i| k0 * p0[i] + k1 * p1[i] + k2 * p2[i] + k3 * p3[i])
+}
+
+/// Edge of a filled contour, going from top to bottom
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    top: [f32; 2],
+    bottom: [f32; 2],
+    winding: i32,
+}
+
+impl Edge {
+    fn x_at(&self, y: f32) -> f32 {
+        self.top[0] + (y - self.top[1]) * self.slope()
+    }
+
+    /// Change in x per unit of y
+    fn slope(&self) -> f32 {
+        (self.bottom[0] - self.top[0]) / (self.bottom[1] - self.top[1])
+    }
+}
+
+/// Splits the area inside the contours into trapezoids. Contours are closed
+/// implicitly, and may be concave, self-intersecting or overlap each other.
+///
+/// The plane is swept top to bottom in horizontal slabs, which end at every
+/// vertex and intersection, so that edges do not cross inside a slab and can
+/// be ordered by x. Only edges spanning the current slab are looked at, and
+/// only neighbors in that order can cross first.
+pub fn fill_trapezoids(contours: &[Contour], rule: FillRule) -> Vec<Trapezoid> {
+    sweep(contours, rule).0
+}
+
+/// Trapezoids of [`fill_trapezoids`], and the lowest and highest winding
+/// number of any area
+fn sweep(contours: &[Contour], rule: FillRule) -> (Vec<Trapezoid>, [i32; 2]) {
+    let mut windings = [0, 0];
+    let mut edges = Vec::new();
+    for contour in contours {
+        let points = &contour.points;
+        for i in 0..points.len() {
+            let p1 = points[i];
+            let p2 = points[(i + 1) % points.len()];
+            if p1[1] < p2[1] {
+                edges.push(Edge {
+                    top: p1,
+                    bottom: p2,
+                    winding: 1,
+                });
+            } else if p1[1] > p2[1] {
+                edges.push(Edge {
+                    top: p2,
+                    bottom: p1,
+                    winding: -1,
+                });
+            }
+        }
+    }
+    edges.retain(|e| e.top.iter().chain(&e.bottom).all(|it| it.is_finite()));
+    edges.sort_by(|a, b| a.top[1].total_cmp(&b.top[1]));
+
+    let mut trapezoids = Vec::new();
+    let mut active: Vec<Edge> = Vec::new();
+    let mut next = 0;
+    let Some(first) = edges.first() else {
+        return (trapezoids, windings);
+    };
+    let mut y1 = first.top[1];
+    loop {
+        while next < edges.len() && edges[next].top[1] <= y1 {
+            active.push(edges[next]);
+            next += 1;
+        }
+        active.retain(|e| e.bottom[1] > y1);
+        if active.is_empty() {
+            match edges.get(next) {
+                Some(e) => y1 = e.top[1],
+                None => break,
+            }
+            continue;
+        }
+
+        // Ordered just below y1, where edges meeting at y1 are told apart by slope
+        active.sort_by(|a, b| {
+            let by_x = a.x_at(y1).total_cmp(&b.x_at(y1));
+            by_x.then_with(|| a.slope().total_cmp(&b.slope()))
+        });
+        let mut y2 = active
+            .iter()
+            .map(|e| e.bottom[1])
+            .fold(f32::INFINITY, f32::min);
+        if let Some(e) = edges.get(next) {
+            y2 = y2.min(e.top[1]);
+        }
+        for pair in active.windows(2) {
+            if let Some(y) = crossing_y(&pair[0], &pair[1], y1, y2) {
+                y2 = y;
+            }
+        }
+
+        let mut winding = 0;
+        let mut left = None;
+        for e in &active {
+            let was_inside = rule.is_inside(winding);
+            winding += e.winding;
+            windings = [windings[0].min(winding), windings[1].max(winding)];
+            match (was_inside, rule.is_inside(winding)) {
+                (false, true) => left = Some(e),
+                (true, false) => {
+                    let l = left.take().unwrap();
+                    trapezoids.push(Trapezoid {
+                        y: [y1, y2],
+                        left: [l.x_at(y1), l.x_at(y2)],
+                        right: [e.x_at(y1), e.x_at(y2)],
+                    });
+                }
+                _ => {}
+            }
+        }
+        y1 = y2;
+    }
+    (trapezoids, windings)
+}
+
+/// Height strictly between `y1` and `y2` at which two edges spanning that
+/// range cross
+fn crossing_y(e1: &Edge, e2: &Edge, y1: f32, y2: f32) -> Option<f32> {
+    // Difference in x is linear in y, look for its sign change
+    let d1 = e1.x_at(y1) - e2.x_at(y1);
+    let d2 = e1.x_at(y2) - e2.x_at(y2);
+    if d1 * d2 >= 0.0 {
+        return None;
+    }
+    let y = y1 + (y2 - y1) * d1 / (d1 - d2);
+    (y1 < y && y < y2).then_some(y)
+}
+
+/// Boundary of the area that [`fill_trapezoids`] fills, as closed loops that
+/// have the inside on their left, i.e. go counter-clockwise with y up. Edges
+/// are cut where they cross, and parts with the same side of the fill on
+/// both sides, like where contours overlap, are left out.
+///
+/// Cutting takes time quadratic in the number of edges, so it is only done
+/// if the contours cross or overlap. Otherwise the sweep finds no winding
+/// numbers but zero and one other, as four areas meet where edges cross,
+/// and the contours already are the outline.
+pub fn fill_outline(contours: &[Contour], rule: FillRule) -> Vec<Vec<[f32; 2]>> {
+    let all_finite = contours
+        .iter()
+        .flat_map(|it| &it.points)
+        .flatten()
+        .all(|it| it.is_finite());
+    let [min, max] = sweep(contours, rule).1;
+    if min == max {
+        return Vec::new();
+    }
+    if all_finite && max - min == 1 {
+        return simple_outline(contours, min < 0);
+    }
+
+    let mut lines = Vec::new();
+    for contour in contours {
+        let points = &contour.points;
+        for i in 0..points.len() {
+            let line = [points[i], points[(i + 1) % points.len()]];
+            if line[0] != line[1] && line.iter().flatten().all(|it| it.is_finite()) {
+                lines.push(line);
+            }
+        }
+    }
+
+    // Points where other lines cross or touch, with how far along they are.
+    // Both lines get the very same point, so that the pieces join up.
+    let mut cuts = vec![Vec::new(); lines.len()];
+    for i in 0..lines.len() {
+        for j in i + 1..lines.len() {
+            let Some([t, u]) = line_intersection(lines[i], lines[j]) else {
+                continue;
+            };
+            let p = match [t, u] {
+                [0.0, _] => lines[i][0],
+                [1.0, _] => lines[i][1],
+                [_, 0.0] => lines[j][0],
+                [_, 1.0] => lines[j][1],
+                _ => lerp(lines[i][0], lines[i][1], t),
+            };
+            if 0.0 < t && t < 1.0 {
+                cuts[i].push((t, p));
+            }
+            if 0.0 < u && u < 1.0 {
+                cuts[j].push((u, p));
+            }
+        }
+    }
+
+    let mut pieces = Vec::new();
+    for (line, mut cuts) in lines.iter().zip(cuts) {
+        cuts.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let points: Vec<_> = (std::iter::once(line[0]))
+            .chain(cuts.into_iter().map(|it| it.1))
+            .chain([line[1]])
+            .collect();
+        for pair in points.windows(2) {
+            if pair[0] == pair[1] {
+                continue;
+            }
+            let [inside_left, inside_right] =
+                sides(&lines, [pair[0], pair[1]]).map(|w| rule.is_inside(w));
+            if inside_left == inside_right {
+                continue;
+            }
+            let piece = if inside_left {
+                [pair[0], pair[1]]
+            } else {
+                [pair[1], pair[0]]
+            };
+            // Lines on top of each other give the same piece more than once
+            if !pieces.contains(&piece) {
+                pieces.push(piece);
+            }
+        }
+    }
+
+    // Follows the pieces from end to start until getting back around
+    let mut used = vec![false; pieces.len()];
+    let mut loops = Vec::new();
+    for first in 0..pieces.len() {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+        let mut points = vec![pieces[first][0]];
+        let mut end = pieces[first][1];
+        while end != points[0] {
+            // Loops touching at a corner are told apart where they come back
+            if let Some(k) = points.iter().position(|&p| p == end) {
+                loops.push(points.split_off(k));
+            }
+            let next = (0..pieces.len()).find(|&k| !used[k] && pieces[k][0] == end);
+            let Some(next) = next else {
+                break;
+            };
+            used[next] = true;
+            points.push(end);
+            end = pieces[next][1];
+        }
+        if points.len() > 1 {
+            loops.push(points);
+        }
+    }
+    loops
+}
+
+/// Outline of contours that neither cross nor overlap, which have the inside
+/// on their left if it is wound negatively. Edges going up count as
+/// positive, so counter-clockwise ones with y up wind their inside as -1.
+fn simple_outline(contours: &[Contour], negative: bool) -> Vec<Vec<[f32; 2]>> {
+    let mut loops = Vec::new();
+    for contour in contours {
+        let mut points = contour.points.clone();
+        points.dedup();
+        if points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        if points.len() < 3 {
+            continue;
+        }
+        if !negative {
+            points.reverse();
+        }
+        loops.push(points);
+    }
+    loops
+}
+
+/// Winding numbers just left and right of a piece of a line, counted like in
+/// [`fill_trapezoids`]. Lines through the middle of the piece are crossed in
+/// between the two sides.
+fn sides(lines: &[[[f32; 2]; 2]], [a, b]: [[f32; 2]; 2]) -> [i32; 2] {
+    let m = lerp(a, b, 0.5);
+    let [dx, dy] = [b[0] - a[0], b[1] - a[1]];
+    // Counted along a ray that crosses the piece, towards -x or -y
+    let along_x = dy.abs() >= dx.abs();
+    let axis = if along_x { 0 } else { 1 };
+    let winding = |[p1, p2]: [[f32; 2]; 2]| {
+        if along_x {
+            (p2[1] - p1[1]).signum() as i32
+        } else {
+            -(p2[0] - p1[0]).signum() as i32
+        }
+    };
+    let [mut before, mut through] = [0, 0];
+    for &line in lines {
+        let [p1, p2] = line;
+        let [u, v] = [p2[0] - p1[0], p2[1] - p1[1]];
+        let [wx, wy] = [m[0] - p1[0], m[1] - p1[1]];
+        let on_line = (u * wy - v * wx).abs() <= 1e-6 * (u * u + v * v)
+            && (0.0..=u * u + v * v).contains(&(u * wx + v * wy));
+        if on_line {
+            through += winding(line);
+            continue;
+        }
+        // Half-open, so that lines meeting at a vertex count once
+        let other = 1 - axis;
+        let [lo, hi] = if p1[other] < p2[other] {
+            [p1, p2]
+        } else {
+            [p2, p1]
+        };
+        if !(lo[other] <= m[other] && m[other] < hi[other]) {
+            continue;
+        }
+        let k = (m[other] - lo[other]) / (hi[other] - lo[other]);
+        if lo[axis] + (hi[axis] - lo[axis]) * k < m[axis] {
+            before += winding(line);
+        }
+    }
+    // The left side is towards -x for lines going down, and towards +y for
+    // lines going right
+    let left_is_before = if along_x { dy > 0.0 } else { dx < 0.0 };
+    if left_is_before {
+        [before, before + through]
+    } else {
+        [before + through, before]
+    }
+}
+
+/// How far along both lines they meet, if they are not parallel
+fn line_intersection([a, b]: [[f32; 2]; 2], [c, d]: [[f32; 2]; 2]) -> Option<[f32; 2]> {
+    let r = [b[0] - a[0], b[1] - a[1]];
+    let s = [d[0] - c[0], d[1] - c[1]];
+    let denom = r[0] * s[1] - r[1] * s[0];
+    if denom == 0.0 {
+        return None;
+    }
+    let ac = [c[0] - a[0], c[1] - a[1]];
+    let t = (ac[0] * s[1] - ac[1] * s[0]) / denom;
+    let u = (ac[0] * r[1] - ac[1] * r[0]) / denom;
+    // Shared endpoints are common, make sure they come out exact
+    let snap = |k: f32| match k {
+        _ if k.abs() < 1e-6 => 0.0,
+        _ if (k - 1.0).abs() < 1e-6 => 1.0,
+        _ => k,
+    };
+    let [t, u] = [snap(t), snap(u)];
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)).then_some([t, u])
+}
+
+fn lerp(a: [f32; 2], b: [f32; 2], t: f32) -> [f32; 2] {
+    [0, 1].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance_to_segment(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+        let ab = [b[0] - a[0], b[1] - a[1]];
+        let ap = [p[0] - a[0], p[1] - a[1]];
+        let len2 = ab[0] * ab[0] + ab[1] * ab[1];
+        let t = ((ap[0] * ab[0] + ap[1] * ab[1]) / len2).clamp(0.0, 1.0);
+        length([ap[0] - ab[0] * t, ap[1] - ab[1] * t])
+    }
+
+    /// Flattens a single curve and checks the chords against points of the
+    /// curve in between, assuming segments are uniform in the curve parameter
+    fn assert_flat(path: &Path, curve: impl Fn(f32) -> [f32; 2], tolerance: f32) -> usize {
+        let contours = path.flatten(tolerance);
+        let points = &contours[0].points;
+        let n = points.len() - 1;
+        for (i, chord) in points.windows(2).enumerate() {
+            for k in 1..8 {
+                let t = (i as f32 + k as f32 / 8.0) / n as f32;
+                let d = distance_to_segment(curve(t), chord[0], chord[1]);
+                assert!(d <= tolerance * 1.01, "{d} > {tolerance} at t = {t}");
+            }
+        }
+        n
+    }
+
+    #[test]
+    fn flatten_quad_within_tolerance() {
+        let [p0, ctrl, p1] = [[0.0, 0.0], [50.0, 100.0], [100.0, 0.0]];
+        let mut path = Path::new();
+        path.move_to(p0);
+        path.quad_to(ctrl, p1);
+        let curve = |t| quad_point(p0, ctrl, p1, t);
+        let counts = [0.01, 0.1, 1.0].map(|tol| assert_flat(&path, curve, tol));
+        assert!(counts[0] > counts[1] && counts[1] > counts[2], "{counts:?}");
+    }
+
+    #[test]
+    fn flatten_cubic_within_tolerance() {
+        let [p0, c1, c2, p1] = [[0.0, 0.0], [0.0, 100.0], [100.0, -100.0], [100.0, 0.0]];
+        let mut path = Path::new();
+        path.move_to(p0);
+        path.cubic_to(c1, c2, p1);
+        let curve = |t| cubic_point(p0, c1, c2, p1, t);
+        let counts = [0.01, 0.1, 1.0].map(|tol| assert_flat(&path, curve, tol));
+        assert!(counts[0] > counts[1] && counts[1] > counts[2], "{counts:?}");
+    }
+
+    fn polygon(points: &[[f32; 2]]) -> Contour {
+        Contour {
+            points: points.to_vec(),
+            closed: true,
+        }
+    }
+
+    fn area(trapezoids: &[Trapezoid]) -> f32 {
+        (trapezoids.iter())
+            .map(|t| {
+                let widths = (t.right[0] - t.left[0]) + (t.right[1] - t.left[1]);
+                0.5 * widths * (t.y[1] - t.y[0])
+            })
+            .sum()
+    }
+
+    fn assert_area(contours: &[Contour], rule: FillRule, expected: f32) {
+        let trapezoids = fill_trapezoids(contours, rule);
+        let actual = area(&trapezoids);
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{rule:?}: expected {expected}, got {actual}"
+        );
+        for t in &trapezoids {
+            assert!(t.y[0] < t.y[1] && t.left[0] <= t.right[0] && t.left[1] <= t.right[1]);
+        }
+    }
+
+    #[test]
+    fn fill_square() {
+        let square = polygon(&[[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]]);
+        assert_area(&[square], FillRule::NonZero, 4.0);
+    }
+
+    #[test]
+    fn fill_concave() {
+        let l_shape = polygon(&[
+            [0.0, 0.0],
+            [2.0, 0.0],
+            [2.0, 1.0],
+            [1.0, 1.0],
+            [1.0, 2.0],
+            [0.0, 2.0],
+        ]);
+        for rule in [FillRule::NonZero, FillRule::EvenOdd] {
+            assert_area(std::slice::from_ref(&l_shape), rule, 3.0);
+        }
+    }
+
+    #[test]
+    fn fill_self_intersecting() {
+        // The halves of a bow-tie wind in opposite directions
+        let bow_tie = polygon(&[[0.0, 0.0], [2.0, 2.0], [2.0, 0.0], [0.0, 2.0]]);
+        for rule in [FillRule::NonZero, FillRule::EvenOdd] {
+            assert_area(std::slice::from_ref(&bow_tie), rule, 2.0);
+        }
+    }
+
+    #[test]
+    fn fill_nested() {
+        let outer = polygon(&[[0.0, 0.0], [4.0, 0.0], [4.0, 4.0], [0.0, 4.0]]);
+        let inner = polygon(&[[1.0, 1.0], [3.0, 1.0], [3.0, 3.0], [1.0, 3.0]]);
+        let contours = [outer, inner];
+        assert_area(&contours, FillRule::NonZero, 16.0);
+        assert_area(&contours, FillRule::EvenOdd, 12.0);
+    }
+
+    /// Signed area, positive for loops going counter-clockwise with y up,
+    /// and perimeter
+    fn measure_outline(loops: &[Vec<[f32; 2]>]) -> [f32; 2] {
+        let [mut area, mut perimeter] = [0.0, 0.0];
+        for points in loops {
+            for (i, &[x1, y1]) in points.iter().enumerate() {
+                let [x2, y2] = points[(i + 1) % points.len()];
+                area += 0.5 * (x1 * y2 - x2 * y1);
+                perimeter += length([x2 - x1, y2 - y1]);
+            }
+        }
+        [area, perimeter]
+    }
+
+    fn assert_outline(contours: &[Contour], rule: FillRule, loops: usize, expected: [f32; 2]) {
+        let outline = fill_outline(contours, rule);
+        let actual = measure_outline(&outline);
+        assert_eq!(outline.len(), loops, "{rule:?}: {outline:?}");
+        for i in 0..2 {
+            assert!(
+                (actual[i] - expected[i]).abs() < 1e-5,
+                "{rule:?}: expected {expected:?}, got {actual:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn outline_keeps_the_edge_of_the_fill() {
+        let outer = polygon(&[[0.0, 0.0], [4.0, 0.0], [4.0, 4.0], [0.0, 4.0]]);
+        let inner = polygon(&[[1.0, 1.0], [3.0, 1.0], [3.0, 3.0], [1.0, 3.0]]);
+        let nested = [outer, inner];
+        assert_outline(&nested, FillRule::NonZero, 1, [16.0, 16.0]);
+        // The hole goes the other way, so its area counts negative
+        assert_outline(&nested, FillRule::EvenOdd, 2, [12.0, 24.0]);
+
+        let overlapping = [
+            polygon(&[[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]]),
+            polygon(&[[1.0, 1.0], [3.0, 1.0], [3.0, 3.0], [1.0, 3.0]]),
+        ];
+        assert_outline(&overlapping, FillRule::NonZero, 1, [7.0, 12.0]);
+        assert_outline(&overlapping, FillRule::EvenOdd, 2, [6.0, 16.0]);
+
+        // Either way round, and cut where it crosses itself
+        let bow_tie = polygon(&[[0.0, 0.0], [2.0, 2.0], [2.0, 0.0], [0.0, 2.0]]);
+        let perimeter = 4.0 + 4.0 * std::f32::consts::SQRT_2;
+        assert_outline(&[bow_tie], FillRule::NonZero, 2, [2.0, perimeter]);
+        let mut reversed = polygon(&[[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]]);
+        reversed.points.reverse();
+        assert_outline(&[reversed], FillRule::NonZero, 1, [4.0, 8.0]);
+
+        // Contours that do not cross are taken as they are
+        let square = polygon(&[[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]]);
+        let mut hole = polygon(&[[0.5, 0.5], [1.5, 0.5], [1.5, 1.5], [0.5, 1.5]]);
+        hole.points.reverse();
+        let outline = fill_outline(&[square.clone(), hole.clone()], FillRule::NonZero);
+        assert_eq!(outline, [square.points, hole.points]);
+    }
+}
@@ -1,4 +1,18 @@
-use crate::font::Font;
+use crate::{
+    font::Font,
+    gradient::Gradient,
+    path::{self, FillRule, Path},
+    program::FrameStats,
+};
+use std::f32::consts::PI;
+
+/// Maximum distance in device pixels between a curve and its approximation
+const TOLERANCE: f32 = 0.25;
+const MAX_ARC_SEGMENTS: usize = 256;
+const MAX_TILES: usize = 4096;
+const MAX_DASHES: usize = 1 << 16;
+/// How many batches back a command may be moved to be merged
+const MAX_BATCH_LOOKBACK: usize = 64;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Default)]
@@ -11,55 +25,619 @@ pub struct Vertex {
 #[derive(Debug, Clone, Default)]
 pub struct Command {
     pub texture: Option<wgpu::Texture>,
+    /// Scissor rect `[x1, y1, x2, y2]` in device pixels
+    pub clip: Option<[u32; 4]>,
     pub idx_off: usize,
     pub idx_cnt: usize,
 }
 
-#[derive(Debug, Clone, Default)]
+/// Draw calls before and after [`PrimitiveList::batch`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    pub commands_before: usize,
+    pub commands_after: usize,
+}
+
+impl BatchStats {
+    pub fn saved(&self) -> usize {
+        self.commands_before - self.commands_after
+    }
+}
+
+/// How the render target starts out each frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    /// Clear to an sRGB color
+    Clear([f32; 4]),
+    /// Keep the contents of the previous frame, e.g. to accumulate strokes
+    Load,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::Clear([0.0, 0.0, 1.0, 1.0])
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineJoin {
+    #[default]
+    Miter,
+    Bevel,
+    Round,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineCap {
+    #[default]
+    Butt,
+    Square,
+    Round,
+}
+
+/// Where the outline of a closed shape goes relative to its edge
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StrokeAlign {
+    #[default]
+    Center,
+    Inner,
+    Outer,
+}
+
+/// Stroke style, the width is in the units of the current coordinate space
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stroke {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /// Ratio of miter length to stroke width above which miters become bevels
+    pub miter_limit: f32,
+    /// Lengths of alternating dashes and gaps, repeated twice if odd like in
+    /// SVG. Zero-length dashes show up as dots with round or square caps.
+    pub dashes: Vec<f32>,
+    /// Distance into the dash pattern at which strokes start
+    pub dash_offset: f32,
+    /// Only used by the outlines of rects, rounded rects and ellipses
+    pub align: StrokeAlign,
+}
+
+impl Default for Stroke {
+    fn default() -> Self {
+        Self {
+            width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+            dashes: Vec::new(),
+            dash_offset: 0.0,
+            align: StrokeAlign::Center,
+        }
+    }
+}
+
+/// Affine map `[a, b, c, d, e, f]` taking `(x, y)` to
+/// `(a x + c y + e, b x + d y + f)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform(pub [f32; 6]);
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+#[allow(unused)]
+impl Transform {
+    pub const IDENTITY: Self = Self([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    pub fn translate([x, y]: [f32; 2]) -> Self {
+        Self([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    pub fn scale([x, y]: [f32; 2]) -> Self {
+        Self([x, 0.0, 0.0, y, 0.0, 0.0])
+    }
+
+    /// Counter-clockwise with y up, i.e. clockwise on screen in `px_space`
+    pub fn rotate(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self([cos, sin, -sin, cos, 0.0, 0.0])
+    }
+
+    pub fn apply(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let [a, b, c, d, e, f] = self.0;
+        [a * x + c * y + e, b * x + d * y + f]
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let [a, b, c, d, ..] = self.0;
+        a * d - b * c
+    }
+}
+
+/// `lhs * rhs` applies `rhs` first
+impl std::ops::Mul for Transform {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let [a, b, c, d, ..] = self.0;
+        let [x, y] = self.apply([rhs.0[4], rhs.0[5]]);
+        Self([
+            a * rhs.0[0] + c * rhs.0[1],
+            b * rhs.0[0] + d * rhs.0[1],
+            a * rhs.0[2] + c * rhs.0[3],
+            b * rhs.0[2] + d * rhs.0[3],
+            x,
+            y,
+        ])
+    }
+}
+
+/// Topology of the vertices between [`PrimitiveList::begin`] and
+/// [`PrimitiveList::end`], like in OpenGL 1
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Triangles,
+    TriangleStrip,
+    TriangleFan,
+    /// Corners go around each quad
+    Quads,
+    /// Drawn `stroke.width` wide
+    Lines,
+    LineStrip,
+}
+
+/// How the edges and the center of a nine-slice panel cover their area
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SliceFill {
+    #[default]
+    Stretch,
+    /// Repeat at the texel size, cutting off the last tile. Panels that would
+    /// take more than `MAX_TILES` tiles are stretched instead.
+    Tile,
+}
+
+/// Part of a texture as `[x1, y1, x2, y2]`, with y going down
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TexRect {
+    Texels([f32; 4]),
+    /// Normalized texture coordinates
+    Uv([f32; 4]),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImageOptions {
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Radians, in the same direction as [`Transform::rotate`]
+    pub rotation: f32,
+    /// Point to rotate around, the center of the destination if `None`
+    pub pivot: Option<[f32; 2]>,
+    /// Overrides `color` for the image if set
+    pub tint: Option<[f32; 4]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shadow {
+    pub offset: [f32; 2],
+    /// Distance over which the edge is blurred, twice the standard deviation
+    /// of the blur like in CSS
+    pub blur: f32,
+    /// Grows the shadow beyond the rect before it is blurred
+    pub spread: f32,
+    pub color: [f32; 4],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Border {
+    pub width: f32,
+    pub color: [f32; 4],
+}
+
+#[derive(Debug, Clone)]
 pub struct PrimitiveList {
     pub texture: Option<wgpu::Texture>,
 
     pub immediate_indices: bool,
+    /// Set between `begin` and `end`, which collect the vertices into `begin_vertices`
+    pub mode: Option<Mode>,
+    pub begin_vertices: Vec<u32>,
     pub px_space: bool,
     pub tex_coord: [f32; 2],
+    /// sRGB-encoded color with straight alpha, see [`crate::program::BlendSpace`]
     pub color: [f32; 4],
+    /// Multiplied with `color` in fills and strokes, so set that to white
+    /// to get the gradient colors as they are
+    pub gradient: Option<Gradient>,
+    pub stroke: Stroke,
+    /// Fade the edges of lines, circles and paths out over a device pixel,
+    /// instead of relying on multisampling. Rounded rects always are.
+    pub feather: bool,
+    /// Applied to coordinates before anything else, including the pixel
+    /// coordinates of `image_rect_i` and `text_i`
+    pub transform: Transform,
+    pub transform_stack: Vec<Transform>,
+    /// Size of the render target in physical pixels
     pub window_size: [u32; 2],
+    /// Physical pixels per logical pixel, used when `logical_px` is set
+    pub scale_factor: f32,
+    /// Treat pixel coordinates as logical pixels, i.e. the window coordinates
+    /// that mouse events are reported in
+    pub logical_px: bool,
+    /// Kept across [`PrimitiveList::clear`], as it belongs to the render target
+    pub background: Background,
+    /// Only what is inside `[x1, y1, x2, y2]` in pixels gets drawn
+    pub clip_rect: Option<[i32; 4]>,
+    /// Attached to the vertices that follow, for [`PrimitiveList::hit_test`]
+    pub hit_id: Option<u64>,
+    /// Lets hit tests through the vertices that follow if they have no
+    /// `hit_id`, like for decorations over what can be hit
+    pub hit_through: bool,
+    /// Vertex indices at which `hit_id` or `hit_through` changed, with their
+    /// new values
+    pub hit_ids: Vec<(u32, Option<u64>, bool)>,
 
     pub commands: Vec<Command>,
     pub idx: Vec<u32>,
     pub vtx: Vec<Vertex>,
 }
 
+impl Default for PrimitiveList {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            immediate_indices: false,
+            mode: None,
+            begin_vertices: Vec::new(),
+            px_space: false,
+            tex_coord: [0.0; 2],
+            color: [0.0; 4],
+            gradient: None,
+            stroke: Stroke::default(),
+            feather: false,
+            transform: Transform::IDENTITY,
+            transform_stack: Vec::new(),
+            window_size: [0; 2],
+            scale_factor: 1.0,
+            logical_px: false,
+            background: Background::default(),
+            clip_rect: None,
+            hit_id: None,
+            hit_through: false,
+            hit_ids: Vec::new(),
+            commands: Vec::new(),
+            idx: Vec::new(),
+            vtx: Vec::new(),
+        }
+    }
+}
+
 impl PrimitiveList {
     pub fn clear(&mut self) {
         self.texture = None;
         self.immediate_indices = false;
+        self.mode = None;
+        self.begin_vertices.clear();
         self.px_space = false;
+        self.logical_px = false;
         self.color = [0.0; 4];
+        self.gradient = None;
+        self.stroke = Stroke::default();
+        self.feather = false;
+        self.clip_rect = None;
+        self.hit_id = None;
+        self.hit_through = false;
+        self.hit_ids.clear();
+        self.transform = Transform::IDENTITY;
+        self.transform_stack.clear();
         self.commands.clear();
         self.idx.clear();
         self.vtx.clear();
     }
 
+    /// Device pixels per pixel, which differ when `logical_px` is set
+    fn px_scale(&self) -> f32 {
+        if self.logical_px {
+            self.scale_factor
+        } else {
+            1.0
+        }
+    }
+
     pub fn px_to_pos(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let scale = self.px_scale();
+        self.device_px_to_pos([x * scale, y * scale])
+    }
+
+    pub fn device_px_to_pos(&self, [x, y]: [f32; 2]) -> [f32; 2] {
         let x = x / self.window_size[0] as f32 * 2.0 - 1.0;
         let y = y / self.window_size[1] as f32 * (-2.0) + 1.0;
         [x, y]
     }
 
+    /// Combines `transform` with the current one until the matching `pop_transform`
+    #[allow(unused)]
+    pub fn push_transform(&mut self, transform: Transform) {
+        self.transform_stack.push(self.transform);
+        self.transform = self.transform * transform;
+    }
+
+    #[allow(unused)]
+    pub fn pop_transform(&mut self) {
+        self.transform = self.transform_stack.pop().unwrap_or_default();
+    }
+
+    /// Maps a point from the current coordinate space to NDC
+    pub fn map_point(&self, coord: [f32; 2]) -> [f32; 2] {
+        let coord = self.transform.apply(coord);
+        if self.px_space {
+            self.px_to_pos(coord)
+        } else {
+            coord
+        }
+    }
+
+    /// Device pixels per unit of the current coordinate space
+    fn px_per_unit(&self) -> f32 {
+        self.transform.determinant().abs().sqrt() * self.px_per_untransformed_unit()
+    }
+
+    fn px_per_untransformed_unit(&self) -> f32 {
+        if !self.px_space {
+            0.5 * self.window_size[0].max(self.window_size[1]) as f32
+        } else {
+            self.px_scale()
+        }
+    }
+
+    /// Inverse of `map_point`, which is affine
+    fn unmap_point_fn(&self) -> impl Fn([f32; 2]) -> [f32; 2] + use<> {
+        let origin = self.map_point([0.0, 0.0]);
+        let ex = sub(self.map_point([1.0, 0.0]), origin);
+        let ey = sub(self.map_point([0.0, 1.0]), origin);
+        let det = cross(ex, ey);
+        move |coord| {
+            let d = sub(coord, origin);
+            [cross(d, ey) / det, cross(ex, d) / det]
+        }
+    }
+
+    /// Maps device pixels to NDC, applying the transform in the current
+    /// coordinate space like for all other primitives. Pixels are logical
+    /// pixels there when `logical_px` is set.
+    fn map_device_px(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        if self.px_space {
+            let scale = self.px_scale();
+            self.map_point([x / scale, y / scale])
+        } else {
+            self.map_point(self.device_px_to_pos([x, y]))
+        }
+    }
+
+    /// Maps a point from the current coordinate space to device pixels
+    fn user_to_device(&self, coord: [f32; 2]) -> [f32; 2] {
+        let [x, y] = self.map_point(coord);
+        let x = (x + 1.0) * 0.5 * self.window_size[0] as f32;
+        let y = (1.0 - y) * 0.5 * self.window_size[1] as f32;
+        [x, y]
+    }
+
+    /// Number of segments that keeps an arc within `TOLERANCE` on screen
+    fn arc_segments(&self, radius: f32, angle: f32) -> usize {
+        let radius_px = radius.abs() * self.px_per_unit();
+        let step = if radius_px > TOLERANCE {
+            2.0 * (1.0 - TOLERANCE / radius_px).acos()
+        } else {
+            PI
+        };
+        ((angle.abs() / step).ceil() as usize).clamp(1, MAX_ARC_SEGMENTS)
+    }
+
     fn last_command(&mut self) -> &mut Command {
+        let clip = self.device_clip_rect();
         let need_push = match self.commands.last() {
             None => true,
-            Some(cmd) => cmd.idx_cnt != 0 && cmd.texture != self.texture,
+            Some(cmd) => cmd.idx_cnt != 0 && (cmd.texture != self.texture || cmd.clip != clip),
         };
         if need_push {
             self.commands.push(Command {
                 texture: self.texture.clone(),
+                clip,
                 idx_off: self.idx.len(),
                 idx_cnt: 0,
             });
         }
-        self.commands.last_mut().unwrap()
+        let cmd = self.commands.last_mut().unwrap();
+        if cmd.idx_cnt == 0 {
+            cmd.texture = self.texture.clone();
+            cmd.clip = clip;
+        }
+        cmd
+    }
+
+    /// `clip_rect` in device pixels, clamped to the render target
+    fn device_clip_rect(&self) -> Option<[u32; 4]> {
+        let [x1, y1, x2, y2] = self.clip_rect?;
+        let scale = self.px_scale();
+        let [w, h] = self.window_size.map(|it| it as f32);
+        let x1 = (x1 as f32 * scale).round().clamp(0.0, w) as u32;
+        let y1 = (y1 as f32 * scale).round().clamp(0.0, h) as u32;
+        let x2 = (x2 as f32 * scale).round().clamp(0.0, w) as u32;
+        let y2 = (y2 as f32 * scale).round().clamp(0.0, h) as u32;
+        Some([x1, y1, x2.max(x1), y2.max(y1)])
+    }
+
+    fn push_vertex(&mut self, vertex: Vertex) -> u32 {
+        let idx = self.vtx.len() as u32;
+        let last = self.hit_ids.last().map_or((None, false), |it| (it.1, it.2));
+        if last != (self.hit_id, self.hit_through) {
+            self.hit_ids.push((idx, self.hit_id, self.hit_through));
+        }
+        self.vtx.push(vertex);
+        idx
+    }
+
+    /// ID attached to a vertex through `hit_id`
+    #[allow(unused)]
+    pub fn vertex_hit_id(&self, idx: u32) -> Option<u64> {
+        self.vertex_hit(idx).0
+    }
+
+    /// `hit_id` and `hit_through` of a vertex
+    fn vertex_hit(&self, idx: u32) -> (Option<u64>, bool) {
+        let pos = self.hit_ids.partition_point(|it| it.0 <= idx);
+        pos.checked_sub(1)
+            .map_or((None, false), |it| (self.hit_ids[it].1, self.hit_ids[it].2))
+    }
+
+    /// Finds the `hit_id` of the topmost primitive under a point in pixels,
+    /// like the positions in mouse events. Primitives without an ID block
+    /// the ones under them and give `None`, unless drawn with `hit_through`.
+    /// Fully transparent triangles never block.
+    #[allow(unused)]
+    pub fn hit_test(&self, [x, y]: [f32; 2]) -> Option<u64> {
+        let scale = self.px_scale();
+        let device = [x * scale, y * scale];
+        let p = self.device_px_to_pos(device);
+        for cmd in self.commands.iter().rev() {
+            if let Some([x1, y1, x2, y2]) = cmd.clip {
+                let [x, y] = device;
+                if x < x1 as f32 || x >= x2 as f32 || y < y1 as f32 || y >= y2 as f32 {
+                    continue;
+                }
+            }
+            let idx = &self.idx[cmd.idx_off..][..cmd.idx_cnt];
+            for tri in idx.chunks_exact(3).rev() {
+                let [a, b, c] = [0, 1, 2].map(|i| self.vtx[tri[i] as usize]);
+                if a.color[3] == 0.0 && b.color[3] == 0.0 && c.color[3] == 0.0 {
+                    continue;
+                }
+                let (id, through) = self.vertex_hit(tri[0]);
+                if id.is_none() && through {
+                    continue;
+                }
+                let d1 = cross(sub(b.coord, a.coord), sub(p, a.coord));
+                let d2 = cross(sub(c.coord, b.coord), sub(p, b.coord));
+                let d3 = cross(sub(a.coord, c.coord), sub(p, c.coord));
+                let has_neg = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+                let has_pos = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+                if !(has_neg && has_pos) {
+                    return id;
+                }
+            }
+        }
+        None
+    }
+
+    /// Counters of what was drawn so far, the rest is filled in by
+    /// [`crate::program::Context::on_frame`]
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            vertices: self.vtx.len(),
+            indices: self.idx.len(),
+            commands: self.commands.iter().filter(|it| it.idx_cnt > 0).count(),
+            ..Default::default()
+        }
+    }
+
+    /// Merges commands with the same texture, to cut down on draw calls.
+    /// Solid fills are first moved to `shared`, a texture with a white texel.
+    /// Commands only move earlier past commands they do not overlap, so the
+    /// result looks the same.
+    pub fn batch(&mut self, shared: Option<(&wgpu::Texture, [u32; 2])>) -> BatchStats {
+        let commands_before = self.commands.iter().filter(|it| it.idx_cnt > 0).count();
+        if let Some((texture, texel)) = shared {
+            self.move_solid_fills(texture, texel);
+        }
+
+        let mut batches: Vec<(Command, Vec<u32>, [f32; 4])> = Vec::new();
+        for cmd in &self.commands {
+            let idx = &self.idx[cmd.idx_off..][..cmd.idx_cnt];
+            if idx.is_empty() {
+                continue;
+            }
+            let mut bounds = [f32::INFINITY, f32::INFINITY, -f32::INFINITY, -f32::INFINITY];
+            for &i in idx {
+                let [x, y] = self.vtx[i as usize].coord;
+                bounds = [
+                    bounds[0].min(x),
+                    bounds[1].min(y),
+                    bounds[2].max(x),
+                    bounds[3].max(y),
+                ];
+            }
+            let mut target = None;
+            for (j, (other, _, other_bounds)) in batches.iter().enumerate().rev() {
+                if other.texture == cmd.texture && other.clip == cmd.clip {
+                    target = Some(j);
+                    break;
+                }
+                let overlap = bounds[0] < other_bounds[2]
+                    && other_bounds[0] < bounds[2]
+                    && bounds[1] < other_bounds[3]
+                    && other_bounds[1] < bounds[3];
+                if overlap || batches.len() - j >= MAX_BATCH_LOOKBACK {
+                    break;
+                }
+            }
+            match target {
+                Some(j) => {
+                    let (_, batch_idx, batch_bounds) = &mut batches[j];
+                    batch_idx.extend_from_slice(idx);
+                    *batch_bounds = [
+                        batch_bounds[0].min(bounds[0]),
+                        batch_bounds[1].min(bounds[1]),
+                        batch_bounds[2].max(bounds[2]),
+                        batch_bounds[3].max(bounds[3]),
+                    ];
+                }
+                None => batches.push((cmd.clone(), idx.to_vec(), bounds)),
+            }
+        }
+
+        self.commands.clear();
+        self.idx.clear();
+        for (mut cmd, idx, _) in batches {
+            cmd.idx_off = self.idx.len();
+            cmd.idx_cnt = idx.len();
+            self.idx.extend(idx);
+            self.commands.push(cmd);
+        }
+        BatchStats {
+            commands_before,
+            commands_after: self.commands.len(),
+        }
+    }
+
+    /// Points the vertices of untextured commands at a white texel of
+    /// `texture`, unless they are shared with textured commands
+    fn move_solid_fills(&mut self, texture: &wgpu::Texture, [x, y]: [u32; 2]) {
+        let tex_coord = [
+            (x as f32 + 0.5) / texture.width() as f32,
+            (y as f32 + 0.5) / texture.height() as f32,
+        ];
+        let mut textured = vec![false; self.vtx.len()];
+        for cmd in self.commands.iter().filter(|it| it.texture.is_some()) {
+            for &i in &self.idx[cmd.idx_off..][..cmd.idx_cnt] {
+                textured[i as usize] = true;
+            }
+        }
+        for cmd in self.commands.iter_mut().filter(|it| it.texture.is_none()) {
+            let idx = &self.idx[cmd.idx_off..][..cmd.idx_cnt];
+            if idx.iter().any(|&i| textured[i as usize]) {
+                continue;
+            }
+            for &i in idx {
+                self.vtx[i as usize].tex_coord = tex_coord;
+            }
+            cmd.texture = Some(texture.clone());
+        }
     }
 
     pub fn push_index(&mut self, idx: u32) {
@@ -69,57 +647,218 @@ impl PrimitiveList {
 
     // OpenGL 1 like API
     #[allow(unused)]
-    pub fn vertex_2f(&mut self, mut coord: [f32; 2]) -> u32 {
-        if self.px_space {
-            coord = self.px_to_pos(coord);
-        }
-        let idx = self.vertex_inner(coord);
-        if self.immediate_indices {
+    pub fn vertex_2f(&mut self, coord: [f32; 2]) -> u32 {
+        let idx = self.vertex_user(coord);
+        if self.mode.is_some() {
+            self.begin_vertices.push(idx);
+        } else if self.immediate_indices {
             self.push_index(idx);
         }
         idx
     }
 
+    #[allow(unused)]
+    pub fn tex_coord_2f(&mut self, tex_coord: [f32; 2]) {
+        self.tex_coord = tex_coord;
+    }
+
+    #[allow(unused)]
+    pub fn color_4f(&mut self, color: [f32; 4]) {
+        self.color = color;
+    }
+
+    #[allow(unused)]
+    pub fn begin(&mut self, mode: Mode) {
+        self.end();
+        self.mode = Some(mode);
+    }
+
+    /// Turns the vertices since `begin` into triangles
+    #[allow(unused)]
+    pub fn end(&mut self) {
+        let Some(mode) = self.mode.take() else {
+            return;
+        };
+        let verts = std::mem::take(&mut self.begin_vertices);
+        match mode {
+            Mode::Triangles => {
+                for tri in verts.chunks_exact(3) {
+                    self.triangle(tri[0], tri[1], tri[2]);
+                }
+            }
+            Mode::TriangleStrip => {
+                for (i, tri) in verts.windows(3).enumerate() {
+                    // keep the winding of the first triangle
+                    if i % 2 == 0 {
+                        self.triangle(tri[0], tri[1], tri[2]);
+                    } else {
+                        self.triangle(tri[1], tri[0], tri[2]);
+                    }
+                }
+            }
+            Mode::TriangleFan => {
+                for pair in verts.get(1..).unwrap_or_default().windows(2) {
+                    self.triangle(verts[0], pair[0], pair[1]);
+                }
+            }
+            Mode::Quads => {
+                for quad in verts.chunks_exact(4) {
+                    self.quad(quad[0], quad[1], quad[3], quad[2]);
+                }
+            }
+            Mode::Lines | Mode::LineStrip => {
+                let ends: Vec<_> = verts.iter().map(|&idx| self.vtx[idx as usize]).collect();
+                // the vertices were only needed for their attributes
+                if verts
+                    .iter()
+                    .enumerate()
+                    .all(|(i, &idx)| idx == verts[0] + i as u32)
+                    && verts
+                        .last()
+                        .is_some_and(|&idx| idx as usize + 1 == self.vtx.len())
+                {
+                    self.vtx.truncate(verts[0] as usize);
+                    self.hit_ids.retain(|it| it.0 < verts[0]);
+                }
+                let step = if mode == Mode::Lines { 2 } else { 1 };
+                for i in (0..ends.len().saturating_sub(1)).step_by(step) {
+                    self.line_quad(ends[i], ends[i + 1]);
+                }
+            }
+        }
+    }
+
+    /// Segment between two vertices in NDC, `stroke.width` wide
+    fn line_quad(&mut self, start: Vertex, end: Vertex) {
+        let to_device = |[x, y]: [f32; 2]| {
+            [
+                (x + 1.0) * 0.5 * self.window_size[0] as f32,
+                (1.0 - y) * 0.5 * self.window_size[1] as f32,
+            ]
+        };
+        let [p1, p2] = [to_device(start.coord), to_device(end.coord)];
+        if p1 == p2 {
+            return;
+        }
+        let dir = normalize(sub(p2, p1));
+        // thinner lines would drop out between pixel centers
+        let half_width = (0.5 * self.stroke.width * self.px_per_unit()).max(0.5);
+        let offset = scale(perp(dir), half_width);
+        let first = self.vtx.len() as u32;
+        for (vertex, p) in [
+            (start, sub(p1, offset)),
+            (start, add(p1, offset)),
+            (end, sub(p2, offset)),
+            (end, add(p2, offset)),
+        ] {
+            self.push_vertex(Vertex {
+                coord: self.device_px_to_pos(p),
+                ..vertex
+            });
+        }
+        self.quad(first, first + 1, first + 2, first + 3);
+    }
+
+    /// Like `vertex_2f`, but never emits indices
+    fn vertex_user(&mut self, coord: [f32; 2]) -> u32 {
+        let coord = self.map_point(coord);
+        self.vertex_inner(coord)
+    }
+
+    fn triangle(&mut self, idx1: u32, idx2: u32, idx3: u32) {
+        self.last_command().idx_cnt += 3;
+        self.idx.push(idx1);
+        self.idx.push(idx2);
+        self.idx.push(idx3);
+    }
+
+    /// Two triangles, the corners go around the quad in the order 1, 2, 4, 3
+    fn quad(&mut self, idx1: u32, idx2: u32, idx3: u32, idx4: u32) {
+        self.triangle(idx1, idx2, idx3);
+        self.triangle(idx4, idx3, idx2);
+    }
+
+    /// Runs a shape generator and applies the gradient to what it emitted
+    fn painted(&mut self, shape: impl FnOnce(&mut Self)) {
+        let vtx_off = self.vtx.len();
+        let idx_off = self.idx.len();
+        shape(self);
+        self.apply_gradient(vtx_off, idx_off);
+    }
+
+    fn apply_gradient(&mut self, vtx_off: usize, idx_off: usize) {
+        let Some(gradient) = self.gradient.take() else {
+            return;
+        };
+        // Only shapes made of their own vertices can be split up
+        if self.idx[idx_off..]
+            .iter()
+            .all(|&idx| idx as usize >= vtx_off)
+        {
+            let to_user = self.unmap_point_fn();
+            let old_vtx: Vec<_> = self.vtx.drain(vtx_off..).collect();
+            let old_idx: Vec<_> = self.idx.drain(idx_off..).collect();
+            if let Some(cmd) = self.commands.last_mut() {
+                cmd.idx_cnt -= old_idx.len();
+            }
+            let mut painted = Vec::new();
+            for tri in old_idx.chunks_exact(3) {
+                let tri = [0, 1, 2].map(|i| old_vtx[tri[i] as usize - vtx_off]);
+                gradient.paint_triangle(tri, &to_user, &mut painted);
+            }
+            for tri in painted.chunks_exact(3) {
+                let [v1, v2, v3] = [0, 1, 2].map(|i| self.push_vertex(tri[i]));
+                self.triangle(v1, v2, v3);
+            }
+        }
+        self.gradient = Some(gradient);
+    }
+
+    fn vertex_device(&mut self, coord: [f32; 2]) -> u32 {
+        let coord = self.device_px_to_pos(coord);
+        self.vertex_inner(coord)
+    }
+
     fn vertex_inner(&mut self, coord: [f32; 2]) -> u32 {
-        let idx = self.vtx.len() as u32;
-        self.vtx.push(Vertex {
+        self.push_vertex(Vertex {
             coord,
             tex_coord: self.tex_coord,
             color: self.color,
-        });
-        idx
+        })
     }
 
+    /// Emitted as a general quad, so it can be rotated by the transform
     pub fn rect_f(&mut self, [x1, y1, x2, y2]: [f32; 4]) {
-        if self.px_space {
-            // vertical ordering is flipped
-            let [x3, y3] = self.px_to_pos([x1, y2]);
-            let [x4, y4] = self.px_to_pos([x2, y1]);
-            self.rect_inner([x3, y3, x4, y4]);
-        } else {
-            self.rect_inner([x1, y1, x2, y2]);
-        }
+        self.painted(|this| {
+            let idx1 = this.vertex_user([x1, y1]);
+            let idx2 = this.vertex_user([x2, y1]);
+            let idx3 = this.vertex_user([x1, y2]);
+            let idx4 = this.vertex_user([x2, y2]);
+            this.quad(idx1, idx2, idx3, idx4);
+        });
     }
 
-    fn rect_inner(&mut self, [x1, y1, x2, y2]: [f32; 4]) {
-        let idx1 = self.vertex_inner([x1, y1]);
-        let idx2 = self.vertex_inner([x2, y1]);
-        let idx3 = self.vertex_inner([x1, y2]);
-        let idx4 = self.vertex_inner([x2, y2]);
-        self.last_command().idx_cnt += 6;
-        self.idx.push(idx1);
-        self.idx.push(idx2);
-        self.idx.push(idx3);
-        self.idx.push(idx4);
-        self.idx.push(idx3);
-        self.idx.push(idx2);
+    /// Snaps a pixel position to physical pixels and picks an integer texel
+    /// scale, so that pixel art stays crisp at fractional scale factors
+    fn snap_px(&self, [x, y]: [i32; 2]) -> ([i32; 2], u32) {
+        let scale = self.px_scale();
+        let x = (x as f32 * scale).round() as i32;
+        let y = (y as f32 * scale).round() as i32;
+        ([x, y], scale.round().max(1.0) as u32)
     }
 
-    pub fn image_rect_i(
+    #[allow(unused)]
+    pub fn image_rect_i(&mut self, dst: [i32; 2], src: [i32; 2], size: [u32; 2]) {
+        let (dst, texel_scale) = self.snap_px(dst);
+        self.image_rect_device(dst, src, size, texel_scale);
+    }
+
+    fn image_rect_device(
         &mut self,
         [dst_x, dst_y]: [i32; 2],
         [src_x, src_y]: [i32; 2],
         [size_x, size_y]: [u32; 2],
+        texel_scale: u32,
     ) {
         let Some(tex) = self.texture.as_ref() else {
             return;
@@ -127,13 +866,16 @@ impl PrimitiveList {
         let tw = tex.size().width;
         let th = tex.size().height;
         let [dst1_x, dst1_y] = [dst_x, dst_y];
-        let [dst2_x, dst2_y] = [dst_x + size_x as i32, dst_y + size_y as i32];
+        let [dst2_x, dst2_y] = [
+            dst_x + (size_x * texel_scale) as i32,
+            dst_y + (size_y * texel_scale) as i32,
+        ];
         let [src1_x, src1_y] = [src_x, src_y];
         let [src2_x, src2_y] = [src_x + size_x as i32, src_y + size_y as i32];
-        let [x1, y2] = self.px_to_pos([dst1_x as f32, dst1_y as f32]);
-        let [x2, y1] = self.px_to_pos([dst2_x as f32, dst2_y as f32]);
-        let [u1, v2] = [src1_x as f32 / tw as f32, src1_y as f32 / th as f32];
-        let [u2, v1] = [src2_x as f32 / tw as f32, src2_y as f32 / th as f32];
+        let [x1, y1] = [dst1_x as f32, dst1_y as f32];
+        let [x2, y2] = [dst2_x as f32, dst2_y as f32];
+        let [u1, v1] = [src1_x as f32 / tw as f32, src1_y as f32 / th as f32];
+        let [u2, v2] = [src2_x as f32 / tw as f32, src2_y as f32 / th as f32];
         let verts = [
             [x1, y1, u1, v1],
             [x2, y1, u2, v1],
@@ -143,8 +885,8 @@ impl PrimitiveList {
         let idx1 = self.vtx.len() as u32;
         self.last_command().idx_cnt += 6;
         for [x, y, u, v] in verts {
-            self.vtx.push(Vertex {
-                coord: [x, y],
+            self.push_vertex(Vertex {
+                coord: self.map_device_px([x, y]),
                 tex_coord: [u, v],
                 color: self.color,
             });
@@ -157,32 +899,2139 @@ impl PrimitiveList {
         self.idx.push(idx1 + 1);
     }
 
-    pub fn text_i(&mut self, font: &Font, [start_x, start_y]: [i32; 2], text: &str) {
-        let old_texture = std::mem::take(&mut self.texture);
-        self.texture = Some(font.texture.clone());
-
-        let mut off_x = 0;
-        let mut off_y = 0;
-        for c in text.chars() {
-            match c {
-                ' ' => off_x += font.glyph_size[0] as i32,
-                '\n' => {
-                    off_x = 0;
-                    off_y += font.glyph_size[1] as i32;
-                }
-                c => {
-                    self.last_command().idx_cnt += 6;
-                    let dst_x = start_x + off_x;
-                    let dst_y = start_y + off_y;
-                    let glyph = font.glyphs.get(&c).unwrap_or(&font.fallback_glyph);
-                    let src_x = glyph[0] as i32;
-                    let src_y = glyph[1] as i32;
-                    self.image_rect_i([dst_x, dst_y], [src_x, src_y], font.glyph_size);
-                    off_x += font.glyph_size[0] as i32;
-                }
-            }
+    /// Size of a texel in the current coordinate space, which is a pixel, or
+    /// a logical pixel if `logical_px` is set. Negative in y outside of
+    /// `px_space`, where y goes up.
+    fn texel_size(&self) -> [f32; 2] {
+        if self.px_space {
+            return [1.0, 1.0];
         }
+        let scale = self.px_scale();
+        [
+            2.0 * scale / self.window_size[0] as f32,
+            -2.0 * scale / self.window_size[1] as f32,
+        ]
+    }
 
-        self.texture = old_texture;
+    fn textured_vertex(&mut self, coord: [f32; 2], tex_coord: [f32; 2]) -> u32 {
+        self.push_vertex(Vertex {
+            coord: self.map_point(coord),
+            tex_coord,
+            color: self.color,
+        })
+    }
+
+    /// Maps the texel rect `src` onto `dst`, both as `[x1, y1, x2, y2]`
+    fn textured_quad(&mut self, [x1, y1, x2, y2]: [f32; 4], src: [f32; 4], tex_size: [f32; 2]) {
+        let [u1, v1, u2, v2] = [
+            src[0] / tex_size[0],
+            src[1] / tex_size[1],
+            src[2] / tex_size[0],
+            src[3] / tex_size[1],
+        ];
+        let idx1 = self.textured_vertex([x1, y1], [u1, v1]);
+        let idx2 = self.textured_vertex([x2, y1], [u2, v1]);
+        let idx3 = self.textured_vertex([x1, y2], [u1, v2]);
+        let idx4 = self.textured_vertex([x2, y2], [u2, v2]);
+        self.quad(idx1, idx2, idx3, idx4);
+    }
+
+    /// Draws part of the current texture scaled to `dst`, in the current
+    /// coordinate space. The top of the image is at the top of `dst`.
+    #[allow(unused)]
+    pub fn image_rect_f(&mut self, dst: [f32; 4], src: TexRect, options: &ImageOptions) {
+        let Some(tex) = self.texture.as_ref() else {
+            return;
+        };
+        let (mut src, tex_size) = match src {
+            TexRect::Texels(rect) => (rect, [tex.width() as f32, tex.height() as f32]),
+            TexRect::Uv(rect) => (rect, [1.0, 1.0]),
+        };
+        if options.flip_x {
+            src.swap(0, 2);
+        }
+        if options.flip_y {
+            src.swap(1, 3);
+        }
+        let [x1, mut y1, x2, mut y2] = dst;
+        if !self.px_space {
+            std::mem::swap(&mut y1, &mut y2);
+        }
+
+        let old_color = self.color;
+        self.color = options.tint.unwrap_or(self.color);
+        let rotated = options.rotation != 0.0;
+        if rotated {
+            let pivot = options.pivot.unwrap_or([0.5 * (x1 + x2), 0.5 * (y1 + y2)]);
+            // NDC units differ in size along x and y unless the window is
+            // square, so rotate in pixels there to keep the image from shearing
+            let [w, h] = if self.px_space {
+                [1.0; 2]
+            } else {
+                self.window_size.map(|it| it.max(1) as f32)
+            };
+            self.push_transform(
+                Transform::translate(pivot)
+                    * Transform::scale([1.0 / w, 1.0 / h])
+                    * Transform::rotate(options.rotation)
+                    * Transform::scale([w, h])
+                    * Transform::translate(scale(pivot, -1.0)),
+            );
+        }
+        self.painted(|this| this.textured_quad([x1, y1, x2, y2], src, tex_size));
+        if rotated {
+            self.pop_transform();
+        }
+        self.color = old_color;
+    }
+
+    /// Draws the texel rect `src` of the current texture as a scalable panel.
+    /// The `insets` (left, top, right and bottom, in texels) mark the corners,
+    /// which are drawn a texel per pixel and only shrink if `dst` is too
+    /// small for them. The edges and center in between are filled by `fill`.
+    #[allow(unused)]
+    pub fn nine_slice_f(
+        &mut self,
+        dst: [f32; 4],
+        src: [u32; 4],
+        insets: [u32; 4],
+        fill: SliceFill,
+    ) {
+        let Some(tex) = self.texture.as_ref() else {
+            return;
+        };
+        let tex_size = [tex.width() as f32, tex.height() as f32];
+        let [x1, mut y1, x2, mut y2] = dst;
+        if !self.px_space {
+            // the top of the texture goes to the top of the rect
+            std::mem::swap(&mut y1, &mut y2);
+        }
+        let texel = self.texel_size();
+        let [src, insets] = [src, insets].map(|it| it.map(|v| v as f32));
+        let [left, top, right, bottom] = insets;
+        let fit = |size: f32, insets: f32, texel: f32| {
+            let needed = insets * texel;
+            if needed != 0.0 {
+                (size / needed).clamp(0.0, 1.0)
+            } else {
+                1.0
+            }
+        };
+        let kx = fit(x2 - x1, left + right, texel[0]) * texel[0];
+        let ky = fit(y2 - y1, top + bottom, texel[1]) * texel[1];
+        let dst_x = [x1, x1 + left * kx, x2 - right * kx, x2];
+        let dst_y = [y1, y1 + top * ky, y2 - bottom * ky, y2];
+        let src_x = [src[0], src[0] + left, src[2] - right, src[2]];
+        let src_y = [src[1], src[1] + top, src[3] - bottom, src[3]];
+        // The center takes columns times rows, and each edge one of them
+        let cols = tile_count([dst_x[1], dst_x[2]], [src_x[1], src_x[2]], texel[0]);
+        let rows = tile_count([dst_y[1], dst_y[2]], [src_y[1], src_y[2]], texel[1]);
+        let tile = fill == SliceFill::Tile && (cols + 2.0) * (rows + 2.0) <= MAX_TILES as f32;
+
+        self.painted(|this| {
+            for j in 0..3 {
+                let tile_y = tile && j == 1;
+                let rows = tile_spans(
+                    [dst_y[j], dst_y[j + 1]],
+                    [src_y[j], src_y[j + 1]],
+                    texel[1],
+                    tile_y,
+                );
+                for i in 0..3 {
+                    let tile_x = tile && i == 1;
+                    let cols = tile_spans(
+                        [dst_x[i], dst_x[i + 1]],
+                        [src_x[i], src_x[i + 1]],
+                        texel[0],
+                        tile_x,
+                    );
+                    for &(dy, sy) in &rows {
+                        for &(dx, sx) in &cols {
+                            let dst = [dx[0], dy[0], dx[1], dy[1]];
+                            let src = [sx[0], sy[0], sx[1], sy[1]];
+                            this.textured_quad(dst, src, tex_size);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn text_i(&mut self, font: &Font, start: [i32; 2], text: &str) {
+        let old_texture = std::mem::take(&mut self.texture);
+        self.texture = Some(font.texture.clone());
+
+        // Glyphs are laid out in physical pixels to keep them on the pixel grid
+        let ([start_x, start_y], texel_scale) = self.snap_px(start);
+        let [advance_x, advance_y] = self.glyph_advance(font);
+        let mut off_x = 0;
+        let mut off_y = 0;
+        for c in text.chars() {
+            match c {
+                ' ' => off_x += advance_x,
+                '\n' => {
+                    off_x = 0;
+                    off_y += advance_y;
+                }
+                c => {
+                    let dst_x = start_x + off_x;
+                    let dst_y = start_y + off_y;
+                    let glyph = font.glyphs.get(&c).unwrap_or(&font.fallback_glyph);
+                    let src_x = glyph[0] as i32;
+                    let src_y = glyph[1] as i32;
+                    let src = [src_x, src_y];
+                    self.image_rect_device([dst_x, dst_y], src, font.glyph_size, texel_scale);
+                    off_x += advance_x;
+                }
+            }
+        }
+
+        self.texture = old_texture;
+    }
+
+    /// Size of `text` as drawn by `text_i`, in the pixels its start is in
+    pub fn text_size(&self, font: &Font, text: &str) -> [f32; 2] {
+        let columns = text.lines().map(|it| it.chars().count()).max().unwrap_or(0);
+        let rows = text.lines().count();
+        let [advance_x, advance_y] = self.glyph_advance(font);
+        let scale = self.px_scale();
+        [
+            (columns as i32 * advance_x) as f32 / scale,
+            (rows as i32 * advance_y) as f32 / scale,
+        ]
+    }
+
+    /// Distance from one glyph to the next in physical pixels
+    fn glyph_advance(&self, font: &Font) -> [i32; 2] {
+        let (_, texel_scale) = self.snap_px([0, 0]);
+        font.glyph_size.map(|it| (it * texel_scale) as i32)
+    }
+
+    #[allow(unused)]
+    pub fn line_f(&mut self, [x1, y1, x2, y2]: [f32; 4]) {
+        self.polyline_f(&[[x1, y1], [x2, y2]], false);
+    }
+
+    #[allow(unused)]
+    pub fn polyline_f(&mut self, points: &[[f32; 2]], closed: bool) {
+        self.painted(|this| this.polyline_inner(points, closed));
+    }
+
+    fn polyline_inner(&mut self, points: &[[f32; 2]], closed: bool) {
+        let mut pts: Vec<[f32; 2]> = Vec::with_capacity(points.len());
+        for &p in points {
+            if pts.last() != Some(&p) {
+                pts.push(p);
+            }
+        }
+        if closed && pts.len() > 1 && pts.first() == pts.last() {
+            pts.pop();
+        }
+        let hw = 0.5 * self.stroke.width;
+        if pts.len() == 1 {
+            self.stroke_dot(pts[0], hw);
+        }
+        if pts.len() < 2 {
+            return;
+        }
+        let closed = closed && pts.len() > 2;
+        if !self.stroke.dashes.is_empty() {
+            let dashes = std::mem::take(&mut self.stroke.dashes);
+            let offset = self.stroke.dash_offset;
+            match dash_polylines(&pts, closed, &dashes, offset) {
+                Some(polylines) => {
+                    for dash in polylines {
+                        self.polyline_inner(&dash, false);
+                    }
+                }
+                None => self.polyline_inner(&pts, closed),
+            }
+            self.stroke.dashes = dashes;
+            return;
+        }
+        if self.feather {
+            self.polyline_feathered(&pts, closed);
+            return;
+        }
+        let n = pts.len();
+        let joints: Vec<_> = (0..n)
+            .map(|i| self.stroke_joint(&pts, i, closed, hw, false))
+            .collect();
+        let ids: Vec<Vec<u32>> = (joints.iter())
+            .map(|joint| joint.points().map(|p| self.vertex_user(p)).collect())
+            .collect();
+        self.stroke_triangles(&joints, &ids, closed, |this, p| this.vertex_user(p));
+    }
+
+    /// Stroke with faded edges, tessellated in device pixels with the same
+    /// joins and caps as without feathering
+    fn polyline_feathered(&mut self, pts: &[[f32; 2]], closed: bool) {
+        let pts: Vec<_> = pts.iter().map(|&p| self.user_to_device(p)).collect();
+        let n = pts.len();
+        let color = self.color;
+        let mut hw = 0.5 * self.stroke.width * self.px_per_unit();
+        if hw < 0.5 {
+            // hairlines keep a pixel wide fringe and fade instead of thinning
+            self.color[3] *= 2.0 * hw;
+            hw = 0.5;
+        }
+        let joints: Vec<_> = (0..n)
+            .map(|i| self.stroke_joint(&pts, i, closed, hw, true))
+            .collect();
+
+        // Where every point of the joints is, as the joint and its index
+        let left: Vec<_> = (joints.iter().enumerate())
+            .flat_map(|(i, joint)| (0..joint.left.len()).map(move |k| (i, k)))
+            .collect();
+        let right: Vec<_> = (joints.iter().enumerate())
+            .flat_map(|(i, joint)| (joint.left.len()..joint.len()).map(move |k| (i, k)))
+            .collect();
+        let point = |&(i, k): &(usize, usize)| joints[i].point(k);
+        let mut ids: Vec<_> = joints.iter().map(|joint| vec![0; joint.len()]).collect();
+        if closed {
+            // The fringe of the inner ring has to point into the hole
+            let left_points: Vec<_> = left.iter().map(point).collect();
+            let right_points: Vec<_> = right.iter().map(point).collect();
+            let left_outer = polygon_area(&left_points).abs() > polygon_area(&right_points).abs();
+            for (slots, points, outer) in [
+                (&left, left_points, left_outer),
+                (&right, right_points, !left_outer),
+            ] {
+                let sign = if outer { 1.0 } else { -1.0 };
+                let offsets: Vec<_> = (feather_offsets(&points).into_iter())
+                    .map(|off| scale(off, sign))
+                    .collect();
+                let ring = self.fringe(&points, &offsets);
+                for (&(i, k), idx) in slots.iter().zip(ring) {
+                    ids[i][k] = idx;
+                }
+            }
+        } else {
+            // Outline going forward on the left and back on the right
+            let slots: Vec<_> = left.iter().chain(right.iter().rev()).copied().collect();
+            let outline: Vec<_> = slots.iter().map(point).collect();
+            let ring = self.fringe(&outline, &feather_offsets(&outline));
+            for (&(i, k), idx) in slots.iter().zip(ring) {
+                ids[i][k] = idx;
+            }
+        }
+        self.stroke_triangles(&joints, &ids, closed, |this, p| this.vertex_device(p));
+        self.color = color;
+    }
+
+    /// Fills the joints of a stroke and the segments between them, given the
+    /// vertices of the points of every joint. `center` adds the vertex that
+    /// joints are fanned around.
+    fn stroke_triangles(
+        &mut self,
+        joints: &[StrokeJoint],
+        ids: &[Vec<u32>],
+        closed: bool,
+        center: impl Fn(&mut Self, [f32; 2]) -> u32,
+    ) {
+        for (joint, ids) in joints.iter().zip(ids) {
+            if joint.fan.is_empty() {
+                continue;
+            }
+            let idx = center(self, joint.center);
+            for &[a, b] in &joint.fan {
+                self.triangle(idx, ids[a], ids[b]);
+            }
+        }
+        let n = joints.len();
+        let segment_count = if closed { n } else { n - 1 };
+        for i in 0..segment_count {
+            let j = (i + 1) % n;
+            let l1 = ids[i][joints[i].left.len() - 1];
+            let r1 = ids[i][joints[i].len() - 1];
+            let [l2, r2] = [ids[j][0], ids[j][joints[j].left.len()]];
+            self.quad(l1, r1, l2, r2);
+        }
+    }
+
+    /// Outline of the stroke around `pts[i]`, which is a cap at either end
+    /// of an open polyline and a join otherwise. Arcs get as many segments
+    /// as needed in the current space, or in device pixels with `device`.
+    fn stroke_joint(
+        &self,
+        pts: &[[f32; 2]],
+        i: usize,
+        closed: bool,
+        hw: f32,
+        device: bool,
+    ) -> StrokeJoint {
+        let n = pts.len();
+        let p = pts[i];
+        let prev = (i > 0 || closed).then(|| pts[(i + n - 1) % n]);
+        let next = (i + 1 < n || closed).then(|| pts[(i + 1) % n]);
+        let user_hw = if device { hw / self.px_per_unit() } else { hw };
+        let arc = |start_dir: [f32; 2], sweep: f32| {
+            let segments = self.arc_segments(user_hw, sweep);
+            (1..segments)
+                .map(|k| {
+                    add(
+                        p,
+                        scale(rotate(start_dir, sweep * k as f32 / segments as f32), hw),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        match (prev, next) {
+            (Some(prev), Some(next)) => self.stroke_join(prev, p, next, hw, arc),
+            (None, Some(next)) => self.stroke_cap(p, normalize(sub(next, p)), hw, true, arc),
+            (Some(prev), None) => self.stroke_cap(p, normalize(sub(p, prev)), hw, false, arc),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    /// Cap at `p`, where the segment has direction `dir`. Round caps go
+    /// around the back of the stroke, on the left at the end and on the
+    /// right at the start, so that the outline stays in order.
+    fn stroke_cap(
+        &self,
+        p: [f32; 2],
+        dir: [f32; 2],
+        hw: f32,
+        at_start: bool,
+        arc: impl Fn([f32; 2], f32) -> Vec<[f32; 2]>,
+    ) -> StrokeJoint {
+        let normal = perp(dir);
+        let outward = if at_start { scale(dir, -1.0) } else { dir };
+        let base = match self.stroke.cap {
+            LineCap::Square => add(p, scale(outward, hw)),
+            LineCap::Butt | LineCap::Round => p,
+        };
+        let left = add(base, scale(normal, hw));
+        let right = add(base, scale(normal, -hw));
+        if self.stroke.cap != LineCap::Round {
+            return StrokeJoint {
+                center: p,
+                left: vec![left],
+                right: vec![right],
+                fan: Vec::new(),
+            };
+        }
+        let sweep = if at_start { PI } else { -PI };
+        let around = arc(normal, sweep);
+        let fan = (0..around.len() + 1).map(|k| [k, k + 1]).collect();
+        let (left, right) = if at_start {
+            (vec![left], around.into_iter().chain([right]).collect())
+        } else {
+            ([left].into_iter().chain(around).collect(), vec![right])
+        };
+        StrokeJoint {
+            center: p,
+            left,
+            right,
+            fan,
+        }
+    }
+
+    /// Join at `p`, between the segments from `prev` and to `next`
+    fn stroke_join(
+        &self,
+        prev: [f32; 2],
+        p: [f32; 2],
+        next: [f32; 2],
+        hw: f32,
+        arc: impl Fn([f32; 2], f32) -> Vec<[f32; 2]>,
+    ) -> StrokeJoint {
+        let dir1 = normalize(sub(p, prev));
+        let dir2 = normalize(sub(next, p));
+        let normal1 = perp(dir1);
+        let normal2 = perp(dir2);
+        let turn = cross(dir1, dir2).atan2(dot(dir1, dir2));
+        if turn.abs() < 1e-4 {
+            return StrokeJoint {
+                center: p,
+                left: vec![add(p, scale(normal1, hw))],
+                right: vec![add(p, scale(normal1, -hw))],
+                fan: Vec::new(),
+            };
+        }
+
+        // The outer side of the join is opposite to the turn
+        let side = if turn > 0.0 { -1.0 } else { 1.0 };
+        let outer1 = add(p, scale(normal1, side * hw));
+        let outer2 = add(p, scale(normal2, side * hw));
+        let cos_half = (0.5 * turn).cos();
+        let miter_dir = normalize(add(normal1, normal2));
+        let outer = match self.stroke.join {
+            LineJoin::Miter if cos_half > 1e-3 && 1.0 / cos_half <= self.stroke.miter_limit => {
+                let tip = add(p, scale(miter_dir, side * hw / cos_half));
+                vec![outer1, tip, outer2]
+            }
+            LineJoin::Miter | LineJoin::Bevel => vec![outer1, outer2],
+            LineJoin::Round => {
+                let around = arc(scale(normal1, side), turn);
+                [outer1].into_iter().chain(around).chain([outer2]).collect()
+            }
+        };
+
+        // Sharing the inner corner avoids overlaps, which would show through
+        // translucent strokes, but only works if both segments are long enough
+        let inner_reach = hw * (0.5 * turn.abs()).tan();
+        let max_reach = 0.5 * length(sub(p, prev)).min(length(sub(next, p)));
+        let shared = cos_half > 1e-3 && inner_reach <= max_reach;
+        let inner = if shared {
+            vec![add(p, scale(miter_dir, -side * hw / cos_half))]
+        } else {
+            vec![
+                add(p, scale(normal1, -side * hw)),
+                add(p, scale(normal2, -side * hw)),
+            ]
+        };
+
+        let (outer_off, inner_off) = if side > 0.0 {
+            (0, outer.len())
+        } else {
+            (inner.len(), 0)
+        };
+        let mut fan: Vec<_> = (1..outer.len())
+            .map(|k| [outer_off + k - 1, outer_off + k])
+            .collect();
+        if shared {
+            fan.push([inner_off, outer_off]);
+            fan.push([outer_off + outer.len() - 1, inner_off]);
+        }
+        let (left, right) = if side > 0.0 {
+            (outer, inner)
+        } else {
+            (inner, outer)
+        };
+        StrokeJoint {
+            center: p,
+            left,
+            right,
+            fan,
+        }
+    }
+
+    /// Zero-length strokes only show up through their caps
+    fn stroke_dot(&mut self, p: [f32; 2], hw: f32) {
+        match self.stroke.cap {
+            LineCap::Butt => {}
+            LineCap::Square => {
+                let idx1 = self.vertex_user([p[0] - hw, p[1] - hw]);
+                let idx2 = self.vertex_user([p[0] + hw, p[1] - hw]);
+                let idx3 = self.vertex_user([p[0] - hw, p[1] + hw]);
+                let idx4 = self.vertex_user([p[0] + hw, p[1] + hw]);
+                self.quad(idx1, idx2, idx3, idx4);
+            }
+            LineCap::Round => {
+                let center = self.vertex_user(p);
+                let first = self.vertex_user(add(p, [hw, 0.0]));
+                self.round_fan(p, center, first, [1.0, 0.0], hw, 2.0 * PI, first);
+            }
+        }
+    }
+
+    /// Triangle fan around `center` from `first` along an arc to `last`
+    #[allow(clippy::too_many_arguments)]
+    fn round_fan(
+        &mut self,
+        p: [f32; 2],
+        center: u32,
+        first: u32,
+        start_dir: [f32; 2],
+        radius: f32,
+        sweep: f32,
+        last: u32,
+    ) {
+        let segments = self.arc_segments(radius, sweep);
+        let mut prev = first;
+        for i in 1..segments {
+            let dir = rotate(start_dir, sweep * i as f32 / segments as f32);
+            let curr = self.vertex_user(add(p, scale(dir, radius)));
+            self.triangle(center, prev, curr);
+            prev = curr;
+        }
+        self.triangle(center, prev, last);
+    }
+
+    // Angles are in radians and go from the x axis towards the y axis,
+    // which is clockwise on screen in `px_space`
+
+    #[allow(unused)]
+    pub fn circle_f(&mut self, center: [f32; 2], radius: f32) {
+        self.ellipse_f(center, [radius, radius]);
+    }
+
+    pub fn ellipse_f(&mut self, center: [f32; 2], radii: [f32; 2]) {
+        let points = self.arc_points(center, radii, 0.0, 2.0 * PI);
+        self.painted(|this| this.fill_fan(center, &points, true));
+    }
+
+    #[allow(unused)]
+    pub fn pie_f(&mut self, center: [f32; 2], radius: f32, [start, end]: [f32; 2]) {
+        let points = self.arc_points(center, [radius, radius], start, end - start);
+        let full_turn = (end - start).abs() >= 2.0 * PI;
+        self.painted(|this| this.fill_fan(center, &points, full_turn));
+    }
+
+    #[allow(unused)]
+    pub fn circle_outline_f(&mut self, center: [f32; 2], radius: f32) {
+        self.ellipse_outline_f(center, [radius, radius]);
+    }
+
+    pub fn ellipse_outline_f(&mut self, center: [f32; 2], radii: [f32; 2]) {
+        let d = self.align_shift();
+        let radii = radii.map(|r| r.abs() + d);
+        let hw = 0.5 * self.stroke.width;
+        if radii[0].min(radii[1]) <= hw {
+            // the inside is covered by the stroke, without overlaps
+            self.ellipse_f(center, radii.map(|r| r + hw));
+            return;
+        }
+        let points = self.arc_points(center, radii, 0.0, 2.0 * PI);
+        self.polyline_f(&points, true);
+    }
+
+    /// How far the center line of outlines is moved out from the edge
+    fn align_shift(&self) -> f32 {
+        match self.stroke.align {
+            StrokeAlign::Center => 0.0,
+            StrokeAlign::Inner => -0.5 * self.stroke.width,
+            StrokeAlign::Outer => 0.5 * self.stroke.width,
+        }
+    }
+
+    /// Strokes the outline of a rect. In `px_space` both sides of the stroke
+    /// are snapped to device pixels, as long as the transform keeps the rect
+    /// axis-aligned.
+    pub fn rect_outline_f(&mut self, [x1, y1, x2, y2]: [f32; 4]) {
+        let d = self.align_shift();
+        let mut rect = [
+            x1.min(x2) - d,
+            y1.min(y2) - d,
+            x1.max(x2) + d,
+            y1.max(y2) + d,
+        ];
+        let old_width = self.stroke.width;
+        let [_, b, c, ..] = self.transform.0;
+        if self.px_space && b == 0.0 && c == 0.0 {
+            (rect, self.stroke.width) = self.snap_outline(rect);
+        }
+        let hw = 0.5 * self.stroke.width;
+        let [x1, y1, x2, y2] = rect;
+        if x2 - x1 <= 2.0 * hw || y2 - y1 <= 2.0 * hw {
+            self.rect_f([x1 - hw, y1 - hw, x2 + hw, y2 + hw]);
+        } else {
+            self.polyline_f(&[[x1, y1], [x2, y1], [x2, y2], [x1, y2]], true);
+        }
+        self.stroke.width = old_width;
+    }
+
+    /// Moves the center line of a rect outline, so that both of its sides
+    /// end up on device pixel boundaries, and returns it with the width
+    fn snap_outline(&self, [x1, y1, x2, y2]: [f32; 4]) -> ([f32; 4], f32) {
+        let px_per_unit = self.px_per_unit();
+        let hw = 0.5 * (self.stroke.width * px_per_unit).round().max(1.0);
+        let [x1, y1] = self.user_to_device([x1, y1]);
+        let [x2, y2] = self.user_to_device([x2, y2]);
+        let snap = |v1: f32, v2: f32| {
+            let (lo, hi) = (v1.min(v2), v1.max(v2));
+            let (lo, hi) = ((lo - hw).round() + hw, (hi + hw).round() - hw);
+            if v1 <= v2 { (lo, hi) } else { (hi, lo) }
+        };
+        let (x1, x2) = snap(x1, x2);
+        let (y1, y2) = snap(y1, y2);
+        let to_user = self.unmap_point_fn();
+        let [x1, y1] = to_user(self.device_px_to_pos([x1, y1]));
+        let [x2, y2] = to_user(self.device_px_to_pos([x2, y2]));
+        ([x1, y1, x2, y2], 2.0 * hw / px_per_unit)
+    }
+
+    /// Strokes the outline of a rounded rect, with radii like `rounded_rect_f`
+    #[allow(unused)]
+    pub fn rounded_rect_outline_f(&mut self, [x1, y1, x2, y2]: [f32; 4], radii: [f32; 4]) {
+        let rect = [x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)];
+        let radii = fit_radii(rect, radii);
+        let d = self.align_shift();
+        let [x1, y1, x2, y2] = [rect[0] - d, rect[1] - d, rect[2] + d, rect[3] + d];
+        let hw = 0.5 * self.stroke.width;
+        if x2 - x1 <= 2.0 * hw || y2 - y1 <= 2.0 * hw {
+            let outer = [x1 - hw, y1 - hw, x2 + hw, y2 + hw];
+            self.rounded_rect_f(outer, radii.map(|r| (r + d + hw).max(0.0)), None);
+            return;
+        }
+        let radii = radii.map(|r| (r + d).max(0.0));
+        let segments = radii.map(|r| self.arc_segments(r + hw, 0.5 * PI));
+        let mut points = rounded_rect_contour([x1, y1, x2, y2], radii, segments);
+        points.dedup();
+        self.polyline_f(&points, true);
+    }
+
+    #[allow(unused)]
+    pub fn arc_f(&mut self, center: [f32; 2], radius: f32, [start, end]: [f32; 2]) {
+        let points = self.arc_points(center, [radius, radius], start, end - start);
+        let full_turn = (end - start).abs() >= 2.0 * PI;
+        self.polyline_f(&points, full_turn);
+    }
+
+    #[allow(unused)]
+    pub fn pie_outline_f(&mut self, center: [f32; 2], radius: f32, [start, end]: [f32; 2]) {
+        let mut points = vec![center];
+        points.extend(self.arc_points(center, [radius, radius], start, end - start));
+        self.polyline_f(&points, true);
+    }
+
+    /// Points along an elliptic arc, a full turn leaves out the closing point
+    fn arc_points(
+        &self,
+        center: [f32; 2],
+        [rx, ry]: [f32; 2],
+        start: f32,
+        sweep: f32,
+    ) -> Vec<[f32; 2]> {
+        let sweep = sweep.clamp(-2.0 * PI, 2.0 * PI);
+        let full_turn = sweep.abs() >= 2.0 * PI;
+        let segments = self
+            .arc_segments(rx.abs().max(ry.abs()), sweep)
+            .max(if full_turn { 3 } else { 1 });
+        let count = if full_turn { segments } else { segments + 1 };
+        (0..count)
+            .map(|i| {
+                let angle = start + sweep * i as f32 / segments as f32;
+                let (sin, cos) = angle.sin_cos();
+                [center[0] + rx * cos, center[1] + ry * sin]
+            })
+            .collect()
+    }
+
+    fn fill_fan(&mut self, center: [f32; 2], points: &[[f32; 2]], closed: bool) {
+        if self.feather {
+            // Pies are star-shaped around the center, so the fan from the
+            // first point of the contour still covers them
+            let contour = (!closed)
+                .then_some(center)
+                .into_iter()
+                .chain(points.iter().copied());
+            let contour: Vec<_> = contour.map(|p| self.user_to_device(p)).collect();
+            let offsets = feather_offsets(&contour);
+            self.fill_convex_feathered(&contour, &offsets);
+            return;
+        }
+        let center = self.vertex_user(center);
+        let first = self.vtx.len() as u32;
+        for &p in points {
+            self.vertex_user(p);
+        }
+        let count = points.len() as u32;
+        for i in 1..count {
+            self.triangle(center, first + i - 1, first + i);
+        }
+        if closed && count > 2 {
+            self.triangle(center, first + count - 1, first);
+        }
+    }
+
+    /// Draws a rectangle with rounded corners and anti-aliased edges.
+    /// Radii go clockwise on screen from the top left corner in `px_space`,
+    /// like in CSS, and are scaled down if they do not fit.
+    #[allow(unused)]
+    pub fn rounded_rect_f(&mut self, rect: [f32; 4], radii: [f32; 4], border: Option<Border>) {
+        self.painted(|this| this.rounded_rect_inner(rect, radii, border));
+    }
+
+    fn rounded_rect_inner(&mut self, rect: [f32; 4], radii: [f32; 4], border: Option<Border>) {
+        let [x1, y1, x2, y2] = rect;
+        let rect = [x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)];
+        let radii = fit_radii(rect, radii);
+        let segments = radii.map(|r| self.arc_segments(r, 0.5 * PI));
+        let outer = rounded_rect_contour(rect, radii, segments);
+        let outer: Vec<_> = outer.into_iter().map(|p| self.user_to_device(p)).collect();
+        let outer_offsets = feather_offsets(&outer);
+
+        let fill_color = self.color;
+        let border = border.filter(|it| it.width > 0.0);
+        let Some(border) = border else {
+            self.fill_convex_feathered(&outer, &outer_offsets);
+            return;
+        };
+
+        let [x1, y1, x2, y2] = rect;
+        let bw = border.width;
+        let inner_rect = [x1 + bw, y1 + bw, x2 - bw, y2 - bw];
+        self.color = border.color;
+        if inner_rect[0] >= inner_rect[2] || inner_rect[1] >= inner_rect[3] {
+            self.fill_convex_feathered(&outer, &outer_offsets);
+            self.color = fill_color;
+            return;
+        }
+        let inner = rounded_rect_contour(inner_rect, radii.map(|r| (r - bw).max(0.0)), segments);
+        let inner: Vec<_> = inner.into_iter().map(|p| self.user_to_device(p)).collect();
+        let inner_offsets = feather_offsets(&inner);
+
+        // The border reaches half a pixel under the fill, so that the fringe
+        // of the fill fades into the border instead of the background
+        let border_outer = self.fringe(&outer, &outer_offsets);
+        let border_inner: Vec<_> = (inner.iter().zip(&inner_offsets))
+            .map(|(&p, &off)| self.vertex_device(add(p, scale(off, -0.5))))
+            .collect();
+        self.band(&border_outer, &border_inner);
+        self.color = fill_color;
+        self.fill_convex_feathered(&inner, &inner_offsets);
+    }
+
+    /// Draws the soft shadow of a rounded rect, which goes under the rect.
+    /// The shadow fades with the distance to its edge like a gaussian blur
+    /// of a straight edge would.
+    pub fn shadow_rect_f(&mut self, rect: [f32; 4], radii: [f32; 4], shadow: &Shadow) {
+        let [x1, y1, x2, y2] = rect;
+        let [dx, dy] = shadow.offset;
+        let spread = shadow.spread;
+        let rect = [
+            x1.min(x2) + dx - spread,
+            y1.min(y2) + dy - spread,
+            x1.max(x2) + dx + spread,
+            y1.max(y2) + dy + spread,
+        ];
+        if rect[0] >= rect[2] || rect[1] >= rect[3] {
+            return;
+        }
+        let radii = fit_radii(rect, radii.map(|r| if r > 0.0 { r + spread } else { r }));
+        let old_color = self.color;
+        self.color = shadow.color;
+
+        let sigma = 0.5 * shadow.blur.max(0.0);
+        let px_per_unit = self.px_per_unit();
+        if sigma * px_per_unit < 0.5 {
+            self.rounded_rect_inner(rect, radii, None);
+            self.color = old_color;
+            return;
+        }
+        // Rings from inside the rect to where the shadow has faded out,
+        // about two device pixels apart
+        let outer = 3.0 * sigma;
+        let inner = outer.min(0.5 * (rect[2] - rect[0]).min(rect[3] - rect[1]));
+        let steps = |extent: f32| ((0.5 * extent * px_per_unit).ceil() as usize).clamp(1, 16);
+        let [inner_steps, outer_steps] = [steps(inner), steps(outer)];
+        let segments = radii.map(|r| self.arc_segments(r + outer, 0.5 * PI));
+        let mut rings = Vec::new();
+        for k in 0..=inner_steps + outer_steps {
+            let d = if k < inner_steps {
+                -inner * (1.0 - k as f32 / inner_steps as f32)
+            } else {
+                outer * (k - inner_steps) as f32 / outer_steps as f32
+            };
+            let ring_rect = [rect[0] - d, rect[1] - d, rect[2] + d, rect[3] + d];
+            let ring_radii = radii.map(|r| (r + d).max(0.0));
+            let alpha = if k == inner_steps + outer_steps {
+                0.0
+            } else {
+                0.5 * (1.0 - erf(d / (sigma * std::f32::consts::SQRT_2)))
+            };
+            self.color[3] = shadow.color[3] * alpha;
+            let ring: Vec<_> = rounded_rect_contour(ring_rect, ring_radii, segments)
+                .into_iter()
+                .map(|p| self.vertex_user(p))
+                .collect();
+            rings.push(ring);
+        }
+        for i in 2..rings[0].len() {
+            self.triangle(rings[0][0], rings[0][i - 1], rings[0][i]);
+        }
+        for pair in rings.windows(2) {
+            self.band(&pair[0], &pair[1]);
+        }
+        self.color = old_color;
+    }
+
+    /// Fills a convex polygon given in device pixels, fading out over one
+    /// pixel around its edge
+    fn fill_convex_feathered(&mut self, points: &[[f32; 2]], offsets: &[[f32; 2]]) {
+        let inner = self.fringe(points, offsets);
+        for i in 2..inner.len() {
+            self.triangle(inner[0], inner[i - 1], inner[i]);
+        }
+    }
+
+    /// Emits a one pixel wide fringe centered on a closed contour in device
+    /// pixels and returns the opaque vertices on its inner side
+    fn fringe(&mut self, points: &[[f32; 2]], offsets: &[[f32; 2]]) -> Vec<u32> {
+        let color = self.color;
+        let inner: Vec<_> = (points.iter().zip(offsets))
+            .map(|(&p, &off)| self.vertex_device(add(p, scale(off, -0.5))))
+            .collect();
+        self.color[3] = 0.0;
+        let outer: Vec<_> = (points.iter().zip(offsets))
+            .map(|(&p, &off)| self.vertex_device(add(p, scale(off, 0.5))))
+            .collect();
+        self.color = color;
+        self.band(&outer, &inner);
+        inner
+    }
+
+    /// Connects two closed rings of vertices with the same number of points
+    fn band(&mut self, ring1: &[u32], ring2: &[u32]) {
+        let n = ring1.len().min(ring2.len());
+        for i in 0..n {
+            let j = (i + 1) % n;
+            self.quad(ring1[i], ring2[i], ring1[j], ring2[j]);
+        }
+    }
+
+    /// Fills the inside of a path, see [`path::fill_trapezoids`]
+    #[allow(unused)]
+    pub fn fill_path(&mut self, path: &Path, rule: FillRule) {
+        let contours = path.flatten(self.path_tolerance());
+        let trapezoids = path::fill_trapezoids(&contours, rule);
+        self.painted(|this| {
+            for trapezoid in trapezoids {
+                let [y1, y2] = trapezoid.y;
+                let idx1 = this.vertex_user([trapezoid.left[0], y1]);
+                let idx2 = this.vertex_user([trapezoid.right[0], y1]);
+                let idx3 = this.vertex_user([trapezoid.left[1], y2]);
+                let idx4 = this.vertex_user([trapezoid.right[1], y2]);
+                for idx in [idx1, idx2, idx3, idx4, idx3, idx2] {
+                    this.push_index(idx);
+                }
+            }
+            if this.feather {
+                // Fades out from the edge of the fill, which is where a point
+                // on the left of the outline goes after mapping to pixels
+                let [o, x, y] =
+                    [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]].map(|p| this.user_to_device(p));
+                let ccw = cross(sub(x, o), sub(y, o)) > 0.0;
+                for outline in path::fill_outline(&contours, rule) {
+                    let points: Vec<_> =
+                        (outline.iter()).map(|&p| this.user_to_device(p)).collect();
+                    let offsets = outward_offsets(&points, ccw);
+                    let points: Vec<_> = (points.iter().zip(&offsets))
+                        .map(|(&p, &off)| add(p, scale(off, 0.5)))
+                        .collect();
+                    this.fringe(&points, &offsets);
+                }
+            }
+        });
+    }
+
+    #[allow(unused)]
+    pub fn stroke_path(&mut self, path: &Path) {
+        for contour in path.flatten(self.path_tolerance()) {
+            self.polyline_f(&contour.points, contour.closed);
+        }
+    }
+
+    #[allow(unused)]
+    pub fn quad_bezier_f(&mut self, p0: [f32; 2], ctrl: [f32; 2], p1: [f32; 2]) {
+        let mut path = Path::new();
+        path.move_to(p0);
+        path.quad_to(ctrl, p1);
+        self.stroke_path(&path);
+    }
+
+    #[allow(unused)]
+    pub fn cubic_bezier_f(&mut self, p0: [f32; 2], ctrl1: [f32; 2], ctrl2: [f32; 2], p1: [f32; 2]) {
+        let mut path = Path::new();
+        path.move_to(p0);
+        path.cubic_to(ctrl1, ctrl2, p1);
+        self.stroke_path(&path);
+    }
+
+    /// Flattening tolerance in the current coordinate space
+    fn path_tolerance(&self) -> f32 {
+        TOLERANCE / self.px_per_unit()
+    }
+}
+
+/// Outline of a stroke around one of its points. Both sides go forward
+/// along the stroke, the segments before and after start and end at their
+/// first and last point.
+struct StrokeJoint {
+    center: [f32; 2],
+    left: Vec<[f32; 2]>,
+    right: Vec<[f32; 2]>,
+    /// Triangles with `center`, as indices into the points
+    fan: Vec<[usize; 2]>,
+}
+
+impl StrokeJoint {
+    fn len(&self) -> usize {
+        self.left.len() + self.right.len()
+    }
+
+    /// Left points followed by the right ones
+    fn point(&self, k: usize) -> [f32; 2] {
+        match self.left.get(k) {
+            Some(&p) => p,
+            None => self.right[k - self.left.len()],
+        }
+    }
+
+    fn points(&self) -> impl Iterator<Item = [f32; 2]> + '_ {
+        self.left.iter().chain(&self.right).copied()
+    }
+}
+
+/// Cuts a polyline into its dashes, which carry the pattern on across
+/// corners. Returns `None` if the pattern has no length.
+fn dash_polylines(
+    points: &[[f32; 2]],
+    closed: bool,
+    dashes: &[f32],
+    offset: f32,
+) -> Option<Vec<Vec<[f32; 2]>>> {
+    if dashes.iter().any(|&it| it < 0.0 || it.is_nan()) {
+        return None;
+    }
+    let mut pattern = dashes.to_vec();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_slice(dashes);
+    }
+    let total: f32 = pattern.iter().sum();
+    if !(total > 0.0 && total.is_finite()) {
+        return None;
+    }
+
+    let mut k = 0;
+    let mut phase = offset.rem_euclid(total);
+    // A zero-length dash right at the start is still a dot there
+    while phase > pattern[k] || (phase == pattern[k] && phase > 0.0) {
+        phase -= pattern[k];
+        k = (k + 1) % pattern.len();
+    }
+    let mut remaining = pattern[k] - phase;
+    let starts_on = k % 2 == 0;
+    let mut on = starts_on;
+
+    let n = points.len();
+    let segment_count = if closed { n } else { n - 1 };
+    let mut polylines = Vec::new();
+    let mut curr = if on { vec![points[0]] } else { Vec::new() };
+    for i in 0..segment_count {
+        let [a, b] = [points[i], points[(i + 1) % n]];
+        let len = length(sub(b, a));
+        let mut t = 0.0;
+        while len - t >= remaining && polylines.len() < MAX_DASHES {
+            t += remaining;
+            let p = add(a, scale(sub(b, a), t / len));
+            curr.push(p);
+            if on {
+                polylines.push(std::mem::take(&mut curr));
+            }
+            on = !on;
+            k = (k + 1) % pattern.len();
+            remaining = pattern[k];
+        }
+        remaining -= len - t;
+        if on {
+            curr.push(b);
+        }
+    }
+    if on && curr.len() > 1 {
+        // A dash over the closing point continues into the first one
+        if closed && starts_on && !polylines.is_empty() {
+            curr.extend_from_slice(&polylines[0][1..]);
+            polylines[0] = curr;
+        } else {
+            polylines.push(curr);
+        }
+    }
+    Some(polylines)
+}
+
+/// Splits a span into tiles of the source size at `texel` units per texel,
+/// the last one showing only the part of the source that fits
+fn tile_spans(dst: [f32; 2], src: [f32; 2], texel: f32, tile: bool) -> Vec<([f32; 2], [f32; 2])> {
+    if dst[0] == dst[1] || src[0] == src[1] {
+        return Vec::new();
+    }
+    let step = (src[1] - src[0]) * texel;
+    let count = tile_count(dst, src, texel);
+    if !tile || !count.is_finite() || count > MAX_TILES as f32 {
+        return vec![(dst, src)];
+    }
+    let count = (count as usize).max(1);
+    (0..count)
+        .map(|k| {
+            let start = dst[0] + k as f32 * step;
+            if k + 1 < count {
+                ([start, start + step], src)
+            } else {
+                let part = (dst[1] - start) / step;
+                ([start, dst[1]], [src[0], src[0] + (src[1] - src[0]) * part])
+            }
+        })
+        .collect()
+}
+
+/// How many tiles of the source size at `texel` units per texel cover a span
+fn tile_count(dst: [f32; 2], src: [f32; 2], texel: f32) -> f32 {
+    if dst[0] == dst[1] || src[0] == src[1] {
+        return 0.0;
+    }
+    ((dst[1] - dst[0]) / ((src[1] - src[0]) * texel)).ceil()
+}
+
+/// Scales corner radii down uniformly until adjacent ones fit the sides
+fn fit_radii([x1, y1, x2, y2]: [f32; 4], radii: [f32; 4]) -> [f32; 4] {
+    let radii = radii.map(|r| r.max(0.0));
+    let [w, h] = [x2 - x1, y2 - y1];
+    let [r1, r2, r3, r4] = radii;
+    let mut k: f32 = 1.0;
+    for (side, sum) in [(w, r1 + r2), (h, r2 + r3), (w, r3 + r4), (h, r4 + r1)] {
+        if sum > side {
+            k = k.min(side / sum);
+        }
+    }
+    radii.map(|r| r * k)
+}
+
+/// Contour of a rounded rectangle, corners are emitted with the given number
+/// of segments even if their radius is zero, so that contours of insets match
+fn rounded_rect_contour(
+    [x1, y1, x2, y2]: [f32; 4],
+    [r1, r2, r3, r4]: [f32; 4],
+    segments: [usize; 4],
+) -> Vec<[f32; 2]> {
+    let corners = [
+        ([x1 + r1, y1 + r1], r1, PI),
+        ([x2 - r2, y1 + r2], r2, 1.5 * PI),
+        ([x2 - r3, y2 - r3], r3, 0.0),
+        ([x1 + r4, y2 - r4], r4, 0.5 * PI),
+    ];
+    let mut points = Vec::new();
+    for (([cx, cy], r, start), n) in corners.into_iter().zip(segments) {
+        for i in 0..=n {
+            let angle = start + 0.5 * PI * i as f32 / n as f32;
+            let (sin, cos) = angle.sin_cos();
+            points.push([cx + r * cos, cy + r * sin]);
+        }
+    }
+    points
+}
+
+/// Outward offsets of a closed contour, such that moving every point by its
+/// offset moves every edge by one unit
+fn feather_offsets(points: &[[f32; 2]]) -> Vec<[f32; 2]> {
+    let n = points.len();
+    let mut area = 0.0;
+    for i in 0..n {
+        area += cross(points[i], points[(i + 1) % n]);
+    }
+    outward_offsets(points, area > 0.0)
+}
+
+/// Offsets like [`feather_offsets`] for a contour that goes counter-clockwise
+/// with y up if `ccw` is set. Sharp corners are only mitered so far, and the
+/// offsets at reflex corners no further than the shorter edge next to them,
+/// so that the fringe stays out of the opposite side of narrow notches.
+fn outward_offsets(points: &[[f32; 2]], ccw: bool) -> Vec<[f32; 2]> {
+    let n = points.len();
+    let outward = if ccw { -1.0 } else { 1.0 };
+    let distinct = |i: usize, j: usize| length(sub(points[i], points[j])) > 1e-4;
+    (0..n)
+        .map(|i| {
+            // Skip over duplicate points, as they have no edge direction
+            let Some(prev) = (1..n).map(|k| (i + n - k) % n).find(|&j| distinct(i, j)) else {
+                return [0.0; 2];
+            };
+            let next = (1..n)
+                .map(|k| (i + k) % n)
+                .find(|&j| distinct(i, j))
+                .unwrap();
+            let [edge1, edge2] = [sub(points[i], points[prev]), sub(points[next], points[i])];
+            let normal1 = scale(perp(normalize(edge1)), outward);
+            let normal2 = scale(perp(normalize(edge2)), outward);
+            let dir = normalize(add(normal1, normal2));
+            let mut miter = 1.0 / dot(dir, normal1).max(0.25);
+            if dot(edge2, normal1) > 0.0 {
+                miter = miter.min(length(edge1).min(length(edge2)).max(1.0));
+            }
+            scale(dir, miter)
+        })
+        .collect()
+}
+
+/// Error function, with an absolute error below 1.5e-7
+fn erf(x: f32) -> f32 {
+    // Abramowitz and Stegun, formula 7.1.26
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly =
+        t * (0.2548296 + t * (-0.28449672 + t * (1.4214138 + t * (-1.4531521 + t * 1.0614054))));
+    (1.0 - poly * (-x * x).exp()).copysign(x)
+}
+
+/// Signed area, positive if the points go counter-clockwise with y up
+fn polygon_area(points: &[[f32; 2]]) -> f32 {
+    let n = points.len();
+    (0..n)
+        .map(|i| 0.5 * cross(points[i], points[(i + 1) % n]))
+        .sum()
+}
+
+fn add([x1, y1]: [f32; 2], [x2, y2]: [f32; 2]) -> [f32; 2] {
+    [x1 + x2, y1 + y2]
+}
+
+fn sub([x1, y1]: [f32; 2], [x2, y2]: [f32; 2]) -> [f32; 2] {
+    [x1 - x2, y1 - y2]
+}
+
+fn scale([x, y]: [f32; 2], k: f32) -> [f32; 2] {
+    [x * k, y * k]
+}
+
+fn dot([x1, y1]: [f32; 2], [x2, y2]: [f32; 2]) -> f32 {
+    x1 * x2 + y1 * y2
+}
+
+fn cross([x1, y1]: [f32; 2], [x2, y2]: [f32; 2]) -> f32 {
+    x1 * y2 - y1 * x2
+}
+
+fn length(v: [f32; 2]) -> f32 {
+    dot(v, v).sqrt()
+}
+
+fn normalize(v: [f32; 2]) -> [f32; 2] {
+    let len = length(v);
+    if len > 0.0 {
+        scale(v, 1.0 / len)
+    } else {
+        [0.0; 2]
+    }
+}
+
+/// Rotated by a quarter turn, from the x axis towards the y axis
+fn perp([x, y]: [f32; 2]) -> [f32; 2] {
+    [-y, x]
+}
+
+fn rotate([x, y]: [f32; 2], angle: f32) -> [f32; 2] {
+    let (sin, cos) = angle.sin_cos();
+    [x * cos - y * sin, x * sin + y * cos]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn list() -> PrimitiveList {
+        PrimitiveList {
+            window_size: [1000, 1000],
+            ..Default::default()
+        }
+    }
+
+    fn stroked(stroke: Stroke) -> PrimitiveList {
+        PrimitiveList { stroke, ..list() }
+    }
+
+    fn area(list: &PrimitiveList) -> f32 {
+        let mut total = 0.0;
+        for tri in list.idx.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| list.vtx[tri[i] as usize].coord);
+            total += 0.5 * cross(sub(b, a), sub(c, a)).abs();
+        }
+        total
+    }
+
+    fn bounds(list: &PrimitiveList) -> [f32; 4] {
+        let mut rect = [f32::INFINITY, f32::INFINITY, -f32::INFINITY, -f32::INFINITY];
+        for &idx in &list.idx {
+            let [x, y] = list.vtx[idx as usize].coord;
+            rect = [
+                rect[0].min(x),
+                rect[1].min(y),
+                rect[2].max(x),
+                rect[3].max(y),
+            ];
+        }
+        rect
+    }
+
+    fn assert_close(actual: f32, expected: f32, eps: f32) {
+        assert!(
+            (actual - expected).abs() <= eps,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn logical_px_without_begin_frame() {
+        let mut list = PrimitiveList {
+            window_size: [100, 100],
+            ..Default::default()
+        };
+        list.logical_px = true;
+        assert_eq!(list.px_to_pos([50.0, 25.0]), [0.0, 0.5]);
+    }
+
+    #[test]
+    fn text_size_matches_glyphs() {
+        let font = Font {
+            texture: testing::texture([64, 64]),
+            glyph_size: [8, 16],
+            glyphs: Default::default(),
+            fallback_glyph: [0, 0],
+            white_texel: [0, 0],
+        };
+        let text = "ab c\nde";
+        for (logical_px, scale_factor) in [(false, 1.5), (true, 1.0), (true, 1.5), (true, 2.25)] {
+            let mut list = PrimitiveList {
+                window_size: [400, 400],
+                logical_px,
+                scale_factor,
+                px_space: true,
+                ..Default::default()
+            };
+            list.text_i(&font, [10, 20], text);
+            let [x1, y1, x2, y2] = bounds(&list);
+            let [width, height] = list.text_size(&font, text);
+            let start = list.px_to_pos([10.0, 20.0]);
+            let end = list.px_to_pos([10.0 + width, 20.0 + height]);
+            // Glyphs start on a physical pixel, so snapping may move them
+            let snap = 1.0 / 400.0;
+            for (a, b) in [(start[0], x1), (end[1], y1), (end[0], x2), (start[1], y2)] {
+                assert_close(a, b, snap + 1e-6);
+            }
+            // Whole texels per pixel, doubled at 1.5 as well
+            let texel = if logical_px {
+                scale_factor.round()
+            } else {
+                1.0
+            };
+            let scale = if logical_px { scale_factor } else { 1.0 };
+            assert_close(width, 32.0 * texel / scale, 1e-4);
+        }
+    }
+
+    #[test]
+    fn line_butt() {
+        let mut list = stroked(Stroke {
+            width: 0.2,
+            ..Default::default()
+        });
+        list.line_f([0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(list.idx.len(), 6);
+        assert_close(area(&list), 0.2, 1e-6);
+        assert_eq!(bounds(&list), [0.0, -0.1, 1.0, 0.1]);
+        assert_eq!(list.commands[0].idx_cnt, list.idx.len());
+    }
+
+    #[test]
+    fn line_square_cap() {
+        let mut list = stroked(Stroke {
+            width: 0.2,
+            cap: LineCap::Square,
+            ..Default::default()
+        });
+        list.line_f([0.0, 0.0, 1.0, 0.0]);
+        assert_close(area(&list), 0.24, 1e-6);
+        assert_eq!(bounds(&list), [-0.1, -0.1, 1.1, 0.1]);
+    }
+
+    #[test]
+    fn line_round_cap() {
+        let mut list = stroked(Stroke {
+            width: 0.2,
+            cap: LineCap::Round,
+            ..Default::default()
+        });
+        list.line_f([0.0, 0.0, 1.0, 0.0]);
+        assert_close(area(&list), 0.2 + PI * 0.01, 1e-3);
+        let [x1, y1, x2, y2] = bounds(&list);
+        assert_close(x1, -0.1, 1e-6);
+        assert_close(x2, 1.1, 1e-6);
+        assert_close(y1, -0.1, 1e-4);
+        assert_close(y2, 0.1, 1e-4);
+    }
+
+    fn corner(join: LineJoin) -> PrimitiveList {
+        let mut list = stroked(Stroke {
+            width: 0.2,
+            join,
+            ..Default::default()
+        });
+        list.polyline_f(&[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]], false);
+        list
+    }
+
+    #[test]
+    fn polyline_joins() {
+        // L shape: 1.1 x 0.2 horizontal bar plus 0.9 x 0.2 vertical bar
+        let miter = corner(LineJoin::Miter);
+        assert_close(area(&miter), 0.4, 1e-5);
+        assert_eq!(bounds(&miter), [0.0, -0.1, 1.1, 1.0]);
+        let bevel = corner(LineJoin::Bevel);
+        assert_close(area(&bevel), 0.4 - 0.005, 1e-5);
+        let round = corner(LineJoin::Round);
+        assert_close(area(&round), 0.4 - 0.01 + PI * 0.0025, 1e-4);
+    }
+
+    #[test]
+    fn feathered_joins_and_caps() {
+        for join in [LineJoin::Miter, LineJoin::Bevel, LineJoin::Round] {
+            for cap in [LineCap::Butt, LineCap::Square, LineCap::Round] {
+                let mut sharp = corner(join);
+                sharp.color = [1.0; 4];
+                sharp.stroke.cap = cap;
+                sharp.idx.clear();
+                sharp.polyline_f(&[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]], false);
+                // The fringe is centered on the edge, so the coverage stays
+                let mut feathered = sharp.clone();
+                feathered.feather = true;
+                feathered.idx.clear();
+                feathered.polyline_f(&[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]], false);
+                assert_close(coverage(&feathered), area(&sharp), 2e-4);
+                let [x1, y1, x2, y2] = bounds(&sharp);
+                let [fx1, fy1, fx2, fy2] = bounds(&feathered);
+                // Half a pixel further out, a little more around arcs
+                for (a, b) in [(x1, fx1), (y1, fy1), (x2, fx2), (y2, fy2)] {
+                    assert!((a - b).abs() < 1.1e-3, "{join:?} {cap:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn polyline_miter_limit() {
+        let mut list = stroked(Stroke {
+            width: 0.2,
+            ..Default::default()
+        });
+        // Nearly reverses direction, so the miter would be very long
+        list.polyline_f(&[[0.0, 0.0], [1.0, 0.0], [0.0, 0.05]], false);
+        let [_, _, x2, _] = bounds(&list);
+        assert!(x2 < 1.2, "miter was not limited: {x2}");
+    }
+
+    #[test]
+    fn polyline_closed() {
+        let mut list = stroked(Stroke {
+            width: 0.2,
+            ..Default::default()
+        });
+        let square = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]];
+        list.polyline_f(&square, true);
+        assert_close(area(&list), 1.2 * 1.2 - 0.8 * 0.8, 1e-5);
+        assert_eq!(bounds(&list), [-0.1, -0.1, 1.1, 1.1]);
+    }
+
+    #[test]
+    fn polyline_px_space() {
+        let mut list = stroked(Stroke {
+            width: 100.0,
+            ..Default::default()
+        });
+        list.px_space = true;
+        list.line_f([0.0, 500.0, 1000.0, 500.0]);
+        assert_close(area(&list), 0.2 * 2.0, 1e-5);
+        let [x1, y1, x2, y2] = bounds(&list);
+        assert_close(x1, -1.0, 1e-6);
+        assert_close(x2, 1.0, 1e-6);
+        assert_close(y1, -0.1, 1e-6);
+        assert_close(y2, 0.1, 1e-6);
+    }
+
+    #[test]
+    fn circle_area() {
+        let mut list = list();
+        list.circle_f([0.0, 0.0], 0.5);
+        assert_close(area(&list), PI * 0.25, 2e-3);
+        assert_close(bounds(&list)[2], 0.5, 1e-6);
+    }
+
+    #[test]
+    fn pie_reversed_sweep() {
+        let mut list = list();
+        list.pie_f([0.0, 0.0], 0.5, [0.5 * PI, 0.0]);
+        assert_close(area(&list), PI * 0.25 / 4.0, 1e-3);
+        for v in &list.vtx {
+            let angle = v.coord[1].atan2(v.coord[0]);
+            assert!(v.coord == [0.0, 0.0] || (-1e-6..=0.5 * PI + 1e-6).contains(&angle));
+        }
+        let [x1, y1, x2, y2] = bounds(&list);
+        assert_close(x1, 0.0, 1e-6);
+        assert_close(y1, 0.0, 1e-6);
+        assert_close(x2, 0.5, 1e-6);
+        assert_close(y2, 0.5, 1e-6);
+    }
+
+    #[test]
+    fn sweep_beyond_full_turn() {
+        // Overlapping triangles would add up to more than the disk
+        let mut list = list();
+        list.pie_f([0.0, 0.0], 0.5, [0.0, 3.0 * PI]);
+        assert_close(area(&list), PI * 0.25, 2e-3);
+
+        let mut list = stroked(Stroke {
+            width: 0.1,
+            ..Default::default()
+        });
+        list.arc_f([0.0, 0.0], 0.5, [0.0, -3.0 * PI]);
+        assert_close(area(&list), PI * (0.55 * 0.55 - 0.45 * 0.45), 2e-3);
+    }
+
+    #[test]
+    fn transform_stack() {
+        let mut list = list();
+        list.push_transform(Transform::translate([0.5, 0.0]));
+        list.push_transform(Transform::rotate(0.5 * PI));
+        list.rect_f([0.0, 0.0, 0.2, 0.1]);
+        let [x1, y1, x2, y2] = bounds(&list);
+        assert_close(x1, 0.4, 1e-6);
+        assert_close(y1, 0.0, 1e-6);
+        assert_close(x2, 0.5, 1e-6);
+        assert_close(y2, 0.2, 1e-6);
+
+        list.pop_transform();
+        assert_eq!(list.transform, Transform::translate([0.5, 0.0]));
+        list.pop_transform();
+        assert_eq!(list.transform, Transform::IDENTITY);
+    }
+
+    #[test]
+    fn transform_moves_text_with_shapes() {
+        let font = Font {
+            texture: testing::texture([64, 64]),
+            glyph_size: [8, 8],
+            glyphs: Default::default(),
+            fallback_glyph: [0, 0],
+            white_texel: [0, 0],
+        };
+        for px_space in [false, true] {
+            let mut rect = list();
+            rect.px_space = px_space;
+            let [offset, corner] = if px_space {
+                [[250.0, 0.0], [8.0, 8.0]]
+            } else {
+                [[0.5, 0.0], [-0.984, 0.984]]
+            };
+            rect.push_transform(Transform::translate(offset));
+            let mut glyph = rect.clone();
+            let origin = if px_space { [0.0, 0.0] } else { [-1.0, 1.0] };
+            rect.rect_f([origin[0], origin[1], corner[0], corner[1]]);
+            glyph.text_i(&font, [0, 0], "a");
+            assert_eq!(bounds(&glyph), bounds(&rect), "px_space {px_space}");
+            assert_close(bounds(&glyph)[0], -0.5, 1e-6);
+        }
+    }
+
+    #[test]
+    fn transform_scales_tolerance() {
+        let mut list = list();
+        list.circle_f([0.0, 0.0], 0.01);
+        let small = list.vtx.len();
+        list.clear();
+        list.push_transform(Transform::scale([50.0, 50.0]));
+        list.circle_f([0.0, 0.0], 0.01);
+        assert!(list.vtx.len() > small);
+    }
+
+    #[test]
+    fn nine_slice_tiles() {
+        let spans = tile_spans([10.0, 35.0], [4.0, 14.0], 1.0, true);
+        assert_eq!(
+            spans,
+            [
+                ([10.0, 20.0], [4.0, 14.0]),
+                ([20.0, 30.0], [4.0, 14.0]),
+                ([30.0, 35.0], [4.0, 9.0]),
+            ]
+        );
+        // y going up outside of px_space
+        let spans = tile_spans([1.0, 0.5], [0.0, 10.0], -0.04, true);
+        assert_eq!(spans.len(), 2);
+        assert_close(spans[1].1[1], 2.5, 1e-5);
+        assert!(tile_spans([0.0, 1.0], [3.0, 3.0], 1.0, true).is_empty());
+
+        // A center of one texel would take millions of tiles, so it stretches
+        let texture = testing::texture([3, 3]);
+        let panel = |size: f32, fill| {
+            let mut list = list();
+            list.px_space = true;
+            list.texture = Some(texture.clone());
+            list.nine_slice_f([0.0, 0.0, size, size], [0, 0, 3, 3], [1; 4], fill);
+            list.vtx.len()
+        };
+        let stretched = panel(2000.0, SliceFill::Stretch);
+        assert!(stretched > 0);
+        assert_eq!(panel(2000.0, SliceFill::Tile), stretched);
+        assert_eq!(panel(12.0, SliceFill::Tile), stretched / 9 * 12 * 12);
+    }
+
+    #[test]
+    fn begin_end_topologies() {
+        let mut list = list();
+        let square = [[0.0, 0.0], [0.5, 0.0], [0.5, 0.5], [0.0, 0.5]];
+        for (mode, verts, idx_cnt) in [
+            (Mode::Triangles, 7, 6),
+            (Mode::TriangleStrip, 5, 9),
+            (Mode::TriangleFan, 5, 9),
+            (Mode::Quads, 8, 12),
+        ] {
+            list.clear();
+            list.begin(mode);
+            for i in 0..verts {
+                list.vertex_2f(square[i % 4]);
+            }
+            list.end();
+            assert_eq!(list.idx.len(), idx_cnt, "{mode:?}");
+        }
+
+        list.clear();
+        list.begin(Mode::Quads);
+        for p in square {
+            list.vertex_2f(p);
+        }
+        list.end();
+        assert_close(area(&list), 0.25, 1e-6);
+
+        list.clear();
+        list.stroke.width = 0.1;
+        list.begin(Mode::LineStrip);
+        list.color_4f([1.0, 0.0, 0.0, 1.0]);
+        list.vertex_2f([0.0, 0.0]);
+        list.color_4f([0.0, 1.0, 0.0, 1.0]);
+        list.vertex_2f([1.0, 0.0]);
+        list.vertex_2f([1.0, 1.0]);
+        list.end();
+        assert_eq!(list.vtx.len(), 8);
+        assert_close(area(&list), 0.2, 1e-5);
+        assert_eq!(list.vtx[0].color, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(list.vtx[2].color, [0.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn fill_path_rules() {
+        let mut path = Path::new();
+        for [x1, y1, x2, y2] in [[-0.5, -0.5, 0.5, 0.5], [-0.25, -0.25, 0.25, 0.25]] {
+            path.move_to([x1, y1]);
+            path.line_to([x2, y1]);
+            path.line_to([x2, y2]);
+            path.line_to([x1, y2]);
+            path.close();
+        }
+        let mut non_zero = list();
+        non_zero.fill_path(&path, FillRule::NonZero);
+        assert_close(area(&non_zero), 1.0, 1e-6);
+        let mut even_odd = list();
+        even_odd.fill_path(&path, FillRule::EvenOdd);
+        assert_close(area(&even_odd), 0.75, 1e-6);
+        assert_eq!(even_odd.commands[0].idx_cnt, even_odd.idx.len());
+    }
+
+    #[test]
+    fn gradient_fill() {
+        let mut list = list();
+        list.color = [1.0, 1.0, 1.0, 0.5];
+        let stops = [(0.0, [1.0, 0.0, 0.0, 1.0]), (1.0, [0.0, 0.0, 1.0, 1.0])];
+        list.gradient = Some(Gradient::linear([-0.5, 0.0], [0.5, 0.0], &stops));
+        list.rect_f([-0.5, -0.5, 0.5, 0.5]);
+        assert_close(area(&list), 1.0, 1e-6);
+        assert_eq!(list.commands[0].idx_cnt, list.idx.len());
+        for &i in &list.idx {
+            let v = list.vtx[i as usize];
+            let t = v.coord[0] + 0.5;
+            assert_close(v.color[0], 1.0 - t, 1e-5);
+            assert_close(v.color[2], t, 1e-5);
+            // Multiplied with the current color
+            assert_close(v.color[3], 0.5, 1e-6);
+        }
+        assert!(list.gradient.is_some());
+    }
+
+    type Corner = ([f32; 2], [f32; 2], [f32; 4]);
+    type Draw = (Option<wgpu::Texture>, Option<[u32; 4]>, [Corner; 3]);
+
+    /// Triangles in the order they are drawn, with what they are drawn with
+    fn draws(list: &PrimitiveList) -> Vec<Draw> {
+        let mut draws = Vec::new();
+        for cmd in &list.commands {
+            for tri in list.idx[cmd.idx_off..][..cmd.idx_cnt].chunks_exact(3) {
+                let tri = [0, 1, 2].map(|i| {
+                    let v = list.vtx[tri[i] as usize];
+                    (v.coord, v.tex_coord, v.color)
+                });
+                draws.push((cmd.texture.clone(), cmd.clip, tri));
+            }
+        }
+        draws
+    }
+
+    /// A solid fill, an image over it and another fill over both
+    fn interleaved(texture: &wgpu::Texture) -> PrimitiveList {
+        let mut list = list();
+        list.color = [1.0, 0.0, 0.0, 1.0];
+        list.rect_f([-0.5, -0.5, 0.0, 0.0]);
+        list.texture = Some(texture.clone());
+        list.color = [1.0; 4];
+        let src = TexRect::Uv([0.0, 0.0, 1.0, 1.0]);
+        list.image_rect_f([-0.25, -0.25, 0.25, 0.25], src, &Default::default());
+        list.texture = None;
+        list.color = [0.0, 0.0, 1.0, 1.0];
+        list.rect_f([-0.1, -0.1, 0.5, 0.5]);
+        list
+    }
+
+    #[test]
+    fn batch_keeps_overlapping_order() {
+        let texture = testing::texture([4, 4]);
+        let mut list = interleaved(&texture);
+        let before = draws(&list);
+        let stats = list.batch(None);
+        assert_eq!(
+            stats,
+            BatchStats {
+                commands_before: 3,
+                commands_after: 3
+            }
+        );
+        assert_eq!(stats.saved(), 0);
+        assert_eq!(draws(&list), before);
+
+        // Once the fills sample a white texel of the same texture, all of it
+        // is one draw, still in the same order
+        let mut list = interleaved(&texture);
+        let stats = list.batch(Some((&texture, [3, 3])));
+        assert_eq!(
+            stats,
+            BatchStats {
+                commands_before: 3,
+                commands_after: 1
+            }
+        );
+        assert_eq!(stats.saved(), 2);
+        let after = draws(&list);
+        assert_eq!(after.len(), before.len());
+        for ((_, _, a), (tex, _, b)) in before.iter().zip(&after) {
+            assert_eq!(tex.as_ref(), Some(&texture));
+            assert_eq!(a.map(|v| (v.0, v.2)), b.map(|v| (v.0, v.2)));
+        }
+        let white = [3.5 / 4.0; 2];
+        assert!(
+            after[..2]
+                .iter()
+                .all(|(_, _, tri)| tri.iter().all(|v| v.1 == white))
+        );
+    }
+
+    #[test]
+    fn batch_merges_past_disjoint_commands() {
+        let texture = testing::texture([4, 4]);
+        let mut list = list();
+        list.color = [1.0; 4];
+        list.rect_f([-1.0, -1.0, -0.5, -0.5]);
+        list.texture = Some(texture.clone());
+        let src = TexRect::Uv([0.0, 0.0, 1.0, 1.0]);
+        list.image_rect_f([-0.25, -0.25, 0.25, 0.25], src, &Default::default());
+        list.texture = None;
+        list.rect_f([0.5, 0.5, 1.0, 1.0]);
+        // Would merge, but is clipped differently
+        list.clip_rect = Some([0, 0, 100, 100]);
+        list.rect_f([-1.0, 0.5, -0.5, 1.0]);
+        list.clip_rect = None;
+
+        let mut before = draws(&list);
+        let stats = list.batch(None);
+        assert_eq!(
+            stats,
+            BatchStats {
+                commands_before: 4,
+                commands_after: 3
+            }
+        );
+        let clips: Vec<_> = list
+            .commands
+            .iter()
+            .map(|it| (it.texture.is_some(), it.clip))
+            .collect();
+        assert_eq!(
+            clips,
+            [(false, None), (true, None), (false, Some([0, 0, 100, 100]))]
+        );
+
+        // The same triangles, only in another order
+        let mut after = draws(&list);
+        let key =
+            |(tex, clip, tri): &Draw| (tex.is_some(), *clip, tri.map(|v| v.0.map(f32::to_bits)));
+        before.sort_by_key(key);
+        after.sort_by_key(key);
+        assert_eq!(after, before);
+    }
+
+    #[test]
+    fn rotated_image_corners() {
+        let texture = testing::texture([2, 2]);
+        let options = ImageOptions {
+            rotation: 0.5 * PI,
+            ..Default::default()
+        };
+        let src = TexRect::Uv([0.0, 0.0, 1.0, 1.0]);
+        // A 200 by 100 px image in the middle of a wide window, once given
+        // in pixels and once in NDC
+        let window_size = [400, 200];
+        let mut px = PrimitiveList {
+            window_size,
+            texture: Some(texture.clone()),
+            px_space: true,
+            ..Default::default()
+        };
+        px.image_rect_f([100.0, 50.0, 300.0, 150.0], src, &options);
+        let mut ndc = PrimitiveList {
+            window_size,
+            texture: Some(texture),
+            ..Default::default()
+        };
+        ndc.image_rect_f([-0.5, -0.5, 0.5, 0.5], src, &options);
+
+        // Turned upright, 100 by 200 px around the center. The top left
+        // goes to the top right in pixels, clockwise on screen, and to the
+        // bottom left in NDC, where y goes up.
+        for (list, sign) in [(&px, 1.0), (&ndc, -1.0)] {
+            for v in &list.vtx {
+                let corner = match v.tex_coord {
+                    [0.0, 0.0] => [0.25, 1.0],
+                    [1.0, 0.0] => [0.25, -1.0],
+                    [1.0, 1.0] => [-0.25, -1.0],
+                    [0.0, 1.0] => [-0.25, 1.0],
+                    other => panic!("{other:?}"),
+                };
+                assert_close(v.coord[0], sign * corner[0], 1e-5);
+                assert_close(v.coord[1], sign * corner[1], 1e-5);
+            }
+        }
+    }
+
+    /// Area weighted by alpha, i.e. the coverage of a feathered shape
+    fn coverage(list: &PrimitiveList) -> f32 {
+        let mut total = 0.0;
+        for tri in list.idx.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| list.vtx[tri[i] as usize]);
+            let alpha = (a.color[3] + b.color[3] + c.color[3]) / 3.0;
+            total += 0.5 * cross(sub(b.coord, a.coord), sub(c.coord, a.coord)).abs() * alpha;
+        }
+        total
+    }
+
+    #[test]
+    fn feathered_fill_matches_plain() {
+        fn polygon(path: &mut Path, points: &[[f32; 2]]) {
+            path.move_to(points[0]);
+            points[1..].iter().for_each(|&p| path.line_to(p));
+            path.close();
+        }
+        let square = |[x, y]: [f32; 2], size: f32| {
+            [[x, y], [x + size, y], [x + size, y + size], [x, y + size]]
+        };
+        let mut l_shape = Path::new();
+        polygon(
+            &mut l_shape,
+            &[
+                [-0.5, -0.5],
+                [0.5, -0.5],
+                [0.5, 0.0],
+                [0.0, 0.0],
+                [0.0, 0.5],
+                [-0.5, 0.5],
+            ],
+        );
+        let mut overlapping = Path::new();
+        polygon(&mut overlapping, &square([-0.5, -0.5], 0.6));
+        polygon(&mut overlapping, &square([-0.1, -0.1], 0.6));
+        let mut nested = Path::new();
+        polygon(&mut nested, &square([-0.5, -0.5], 1.0));
+        polygon(&mut nested, &square([-0.25, -0.25], 0.5));
+        let mut bow_tie = Path::new();
+        polygon(
+            &mut bow_tie,
+            &[[-0.5, -0.5], [0.5, 0.5], [0.5, -0.5], [-0.5, 0.5]],
+        );
+
+        // Perimeter of what is filled, the fringe adds half a pixel around it
+        let diagonal = std::f32::consts::SQRT_2;
+        for (path, rule, perimeter) in [
+            (&l_shape, FillRule::NonZero, 4.0),
+            (&overlapping, FillRule::NonZero, 4.0 * 0.6 + 4.0 * 0.4),
+            (
+                &overlapping,
+                FillRule::EvenOdd,
+                4.0 * 0.6 + 4.0 * 0.4 + 4.0 * 0.2,
+            ),
+            (&nested, FillRule::NonZero, 4.0),
+            (&nested, FillRule::EvenOdd, 6.0),
+            (&bow_tie, FillRule::NonZero, 2.0 + 2.0 * diagonal),
+        ] {
+            let mut plain = list();
+            plain.color = [1.0; 4];
+            plain.fill_path(path, rule);
+            let mut feathered = plain.clone();
+            feathered.idx.clear();
+            feathered.feather = true;
+            feathered.fill_path(path, rule);
+            // Mitered corners add a little more, the sharp ones most
+            let expected = area(&plain) + 0.001 * perimeter;
+            assert_close(coverage(&feathered), expected, 5e-5);
+            let [x1, y1, x2, y2] = bounds(&plain);
+            let [fx1, fy1, fx2, fy2] = bounds(&feathered);
+            // A pixel out, or a few at the tips of the bow-tie
+            for (a, b) in [(x1, fx1), (y1, fy1), (x2, fx2), (y2, fy2)] {
+                assert!((a - b).abs() <= 6e-3, "{rule:?}: {a} {b}");
+            }
+        }
+    }
+
+    /// Coverage of the triangles with the given color, ignoring alpha
+    fn coverage_of(list: &PrimitiveList, rgb: [f32; 3]) -> f32 {
+        let mut filtered = list.clone();
+        filtered.idx = (list.idx.chunks_exact(3))
+            .filter(|tri| tri.iter().all(|&i| list.vtx[i as usize].color[..3] == rgb))
+            .flatten()
+            .copied()
+            .collect();
+        coverage(&filtered)
+    }
+
+    #[test]
+    fn rounded_rect_uneven_radii() {
+        let mut list = list();
+        list.color = [1.0; 4];
+        list.rounded_rect_f([-0.5, -0.5, 0.5, 0.5], [0.0, 0.1, 0.2, 0.3], None);
+        let cut = (1.0 - 0.25 * PI) * (0.01 + 0.04 + 0.09);
+        assert_close(coverage(&list), 1.0 - cut, 2e-3);
+        let [x1, y1, x2, y2] = bounds(&list);
+        assert_close(x1, -0.501, 1e-4);
+        assert_close(y1, -0.501, 1e-4);
+        assert_close(x2, 0.501, 1e-4);
+        assert_close(y2, 0.501, 1e-4);
+    }
+
+    #[test]
+    fn rounded_rect_clamps_radii() {
+        // The short side only has room for radii of 0.25, which makes a stadium
+        let mut list = list();
+        list.color = [1.0; 4];
+        list.rounded_rect_f([-0.5, -0.25, 0.5, 0.25], [1.0; 4], None);
+        assert_close(coverage(&list), 0.5 - (1.0 - 0.25 * PI) * 0.25, 2e-3);
+        let [x1, y1, x2, y2] = bounds(&list);
+        assert_close(x1, -0.501, 1e-4);
+        assert_close(y1, -0.251, 1e-4);
+        assert_close(x2, 0.501, 1e-4);
+        assert_close(y2, 0.251, 1e-4);
+    }
+
+    #[test]
+    fn rounded_rect_border() {
+        let mut list = list();
+        list.color = [0.0, 1.0, 0.0, 1.0];
+        let border = Border {
+            width: 0.1,
+            color: [1.0, 0.0, 0.0, 1.0],
+        };
+        // Thicker than the radius, so the inner corners are square
+        list.rounded_rect_f([-0.5, -0.5, 0.5, 0.5], [0.05; 4], Some(border));
+        let fill = coverage_of(&list, [0.0, 1.0, 0.0]);
+        assert_close(fill, 0.8 * 0.8, 2e-3);
+        for &i in &list.idx {
+            let v = list.vtx[i as usize];
+            if v.color[..3] == [0.0, 1.0, 0.0] {
+                assert!(v.coord.iter().all(|c| c.abs() <= 0.401 + 1e-6), "{v:?}");
+            }
+        }
+        // Reaches half a pixel under the fill
+        let outer = 1.0 - (1.0 - 0.25 * PI) * 4.0 * 0.0025;
+        let overlap = 4.0 * 0.8 * 0.001;
+        assert_close(
+            coverage_of(&list, [1.0, 0.0, 0.0]),
+            outer - fill + overlap,
+            2e-3,
+        );
+    }
+
+    #[test]
+    fn feathered_shapes() {
+        let mut list = stroked(Stroke {
+            width: 0.1,
+            ..Default::default()
+        });
+        list.feather = true;
+        list.color = [1.0; 4];
+        list.line_f([-0.5, 0.0, 0.5, 0.0]);
+        assert_close(coverage(&list), 0.1, 1e-4);
+        assert!(list.vtx.iter().any(|v| v.color[3] == 0.0));
+
+        list.idx.clear();
+        list.stroke.cap = LineCap::Round;
+        list.line_f([-0.5, 0.0, 0.5, 0.0]);
+        assert_close(coverage(&list), 0.1 + PI * 0.05 * 0.05, 1e-3);
+
+        list.idx.clear();
+        list.polyline_f(&[[-0.5, -0.5], [0.5, -0.5], [0.5, 0.5], [-0.5, 0.5]], true);
+        assert_close(coverage(&list), 1.1 * 1.1 - 0.9 * 0.9, 1e-3);
+
+        list.idx.clear();
+        list.circle_f([0.0, 0.0], 0.5);
+        assert_close(coverage(&list), PI * 0.25, 2e-3);
+
+        list.idx.clear();
+        let mut path = Path::new();
+        path.move_to([-0.5, -0.5]);
+        path.line_to([0.5, -0.5]);
+        path.line_to([0.5, 0.5]);
+        path.line_to([-0.5, 0.5]);
+        list.fill_path(&path, FillRule::NonZero);
+        // the fringe adds half a pixel around the edge
+        assert_close(coverage(&list), 1.0 + 4.0 * 0.001, 1e-4);
+
+        // Hairlines fade instead of getting thinner than a pixel
+        list.idx.clear();
+        list.stroke.width = 0.0005;
+        list.line_f([-0.5, 0.0, 0.5, 0.0]);
+        assert_close(coverage(&list), 0.0005, 1e-5);
+        assert_close(bounds(&list)[3], 0.002, 1e-5);
+    }
+
+    #[test]
+    fn dashes_follow_corners() {
+        let square = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let dashes = dash_polylines(&square, false, &[0.75, 0.5], 0.0).unwrap();
+        assert_eq!(
+            dashes,
+            [
+                vec![[0.0, 0.0], [0.75, 0.0]],
+                vec![[1.0, 0.25], [1.0, 1.0]],
+                vec![[0.5, 1.0], [0.0, 1.0]],
+            ]
+        );
+
+        // The last dash wraps around into the first one
+        let dashes = dash_polylines(&square, true, &[0.75, 0.5], 0.0).unwrap();
+        assert_eq!(dashes.len(), 3);
+        assert_eq!(dashes[0], [[0.0, 0.25], [0.0, 0.0], [0.75, 0.0]]);
+        assert_eq!(dashes[2], [[0.5, 1.0], [0.0, 1.0], [0.0, 0.75]]);
+
+        let dashes = dash_polylines(&square, false, &[0.5], 0.25).unwrap();
+        assert_eq!(dashes[0], [[0.0, 0.0], [0.25, 0.0]]);
+        assert_eq!(dashes[1], [[0.75, 0.0], [1.0, 0.0], [1.0, 0.25]]);
+
+        assert!(dash_polylines(&square, false, &[0.0, 0.0], 0.0).is_none());
+
+        // Zero-length dashes are dots, the first one on the start
+        let dashes = dash_polylines(&[[0.0, 0.0], [10.0, 0.0]], false, &[0.0, 5.0], 0.0).unwrap();
+        assert_eq!(
+            dashes,
+            [
+                vec![[0.0, 0.0], [0.0, 0.0]],
+                vec![[5.0, 0.0], [5.0, 0.0]],
+                vec![[10.0, 0.0], [10.0, 0.0]],
+            ]
+        );
+    }
+
+    #[test]
+    fn dashed_stroke() {
+        let mut list = stroked(Stroke {
+            width: 0.1,
+            dashes: vec![0.2, 0.3],
+            ..Default::default()
+        });
+        list.line_f([0.0, 0.0, 1.0, 0.0]);
+        assert_close(area(&list), 0.04, 1e-6);
+
+        // Animating the offset shifts the dashes along the line
+        let mut list = stroked(Stroke {
+            width: 0.1,
+            dashes: vec![0.2, 0.3],
+            dash_offset: 0.1,
+            ..Default::default()
+        });
+        list.line_f([0.0, 0.0, 1.0, 0.0]);
+        assert_close(area(&list), 0.04, 1e-6);
+        assert_eq!(list.idx.len(), 3 * 6);
+    }
+
+    #[test]
+    fn shadow_keeps_coverage() {
+        let mut list = list();
+        let shadow = Shadow {
+            offset: [0.1, 0.0],
+            blur: 0.1,
+            spread: 0.0,
+            color: [0.0, 0.0, 0.0, 1.0],
+        };
+        list.shadow_rect_f([-0.5, -0.5, 0.5, 0.5], [0.0; 4], &shadow);
+        // Blurring moves coverage across edges without changing it
+        assert_close(coverage(&list), 1.0, 1e-2);
+        let [x1, _, x2, _] = bounds(&list);
+        assert_close(x1, -0.55, 1e-5);
+        assert_close(x2, 0.75, 1e-5);
+        assert_close(erf(0.5), 0.5204999, 1e-6);
+    }
+
+    #[test]
+    fn rect_outline_snaps_to_pixels() {
+        let mut list = list();
+        list.window_size = [200, 100];
+        list.px_space = true;
+        list.logical_px = true;
+        list.scale_factor = 1.5;
+        list.stroke.align = StrokeAlign::Inner;
+        list.rect_outline_f([10.0, 10.0, 50.0, 31.0]);
+        let device: Vec<_> = (list.vtx.iter())
+            .map(|v| [(v.coord[0] + 1.0) * 100.0, (1.0 - v.coord[1]) * 50.0])
+            .collect();
+        for [x, y] in &device {
+            assert_close(*x, x.round(), 1e-3);
+            assert_close(*y, y.round(), 1e-3);
+        }
+        // 1.5 device pixels round up to 2 on both sides
+        assert_close(area(&list) * 100.0 * 50.0, 60.0 * 32.0 - 56.0 * 28.0, 1e-1);
+
+        // Without overlaps, so translucent outlines stay even
+        let mut outer = stroked(Stroke {
+            width: 0.1,
+            align: StrokeAlign::Outer,
+            ..Default::default()
+        });
+        outer.rect_outline_f([-0.5, -0.5, 0.5, 0.5]);
+        assert_close(area(&outer), 1.2 * 1.2 - 1.0, 1e-5);
+        let mut inner = stroked(Stroke {
+            width: 0.1,
+            align: StrokeAlign::Inner,
+            ..Default::default()
+        });
+        inner.circle_outline_f([0.0, 0.0], 0.5);
+        assert_close(area(&inner), PI * (0.25 - 0.16), 2e-3);
+        assert_close(bounds(&inner)[2], 0.5, 1e-3);
+    }
+
+    #[test]
+    fn hit_test_topmost() {
+        let mut list = list();
+        list.window_size = [100, 100];
+        list.px_space = true;
+        list.color = [1.0; 4];
+        list.hit_id = Some(1);
+        list.rect_f([10.0, 10.0, 60.0, 60.0]);
+        list.hit_id = Some(2);
+        list.circle_f([50.0, 50.0], 20.0);
+        list.hit_id = None;
+        list.hit_through = true;
+        list.rect_f([0.0, 0.0, 100.0, 100.0]);
+        list.hit_through = false;
+
+        assert_eq!(list.hit_test([20.0, 20.0]), Some(1));
+        assert_eq!(list.hit_test([50.0, 50.0]), Some(2));
+        assert_eq!(list.hit_test([90.0, 10.0]), None);
+
+        list.hit_id = Some(3);
+        list.clip_rect = Some([0, 0, 45, 100]);
+        list.rect_f([0.0, 0.0, 100.0, 100.0]);
+        assert_eq!(list.hit_test([40.0, 50.0]), Some(3));
+        assert_eq!(list.hit_test([50.0, 50.0]), Some(2));
+        assert_eq!(list.commands.last().unwrap().clip, Some([0, 0, 45, 100]));
+
+        // Without an ID, the topmost primitive hides what is under it
+        list.hit_id = None;
+        list.clip_rect = None;
+        list.rect_f([0.0, 40.0, 100.0, 60.0]);
+        assert_eq!(list.hit_test([50.0, 50.0]), None);
+        assert_eq!(list.hit_test([40.0, 30.0]), Some(3));
+        assert_eq!(list.hit_test([50.0, 30.0]), Some(2));
     }
 }
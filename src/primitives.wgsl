@@ -14,6 +14,16 @@ struct VertexOut {
 @group(0) @binding(0) var prim_texture: texture_2d<f32>;
 @group(0) @binding(1) var prim_sampler: sampler;
 
+// Set when the render target is sRGB: the hardware then encodes our output,
+// so sRGB input colors have to be decoded first
+override LINEAR_BLENDING: bool = false;
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let lo = c / 12.92;
+    let hi = pow((c + 0.055) / 1.055, vec3(2.4));
+    return select(hi, lo, c <= vec3(0.04045));
+}
+
 @vertex
 fn vs_main(in: VertexIn) -> VertexOut {
     var out: VertexOut;
@@ -27,5 +37,10 @@ fn vs_main(in: VertexIn) -> VertexOut {
 fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
     let tint = in.color;
     let sampled = textureSample(prim_texture, prim_sampler, in.tex_coord);
-    return tint * sampled;
+    // Modulate in sRGB so that both blend spaces produce the same opaque colors
+    var color = tint * sampled;
+    if LINEAR_BLENDING {
+        color = vec4(srgb_to_linear(color.rgb), color.a);
+    }
+    return color;
 }
@@ -1,15 +1,128 @@
-use sdl3::{EventPump, video::Window};
-use std::{array, mem};
+use sdl3::{EventPump, VideoSubsystem, video::Window};
+use std::{
+    array,
+    collections::HashMap,
+    fs,
+    io::{self, BufReader, BufWriter, Write},
+    mem,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 use wgpu::wgt;
 
-use crate::primitives::{PrimitiveList, Vertex};
+use crate::{
+    capture,
+    font::Font,
+    primitives::{Background, PrimitiveList, Vertex},
+};
 
 pub const STAGING_BUFFER_SIZE: u64 = 1 << 24;
 
+/// Color space in which alpha blending happens.
+///
+/// Colors given to [`PrimitiveList`] and texels of sampled textures are always
+/// treated as sRGB-encoded, so opaque colors look the same in both modes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendSpace {
+    /// Blend sRGB-encoded values directly, as most design tools and browsers do
+    #[default]
+    Srgb,
+    /// Decode colors to linear before blending, encode back when storing
+    Linear,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ContextConfig {
+    /// Preferred blend space, the actual one depends on available surface formats
+    pub blend_space: BlendSpace,
+    /// Directory to persist compiled pipelines in, where the adapter supports it
+    pub pipeline_cache_dir: Option<PathBuf>,
+}
+
+/// Rendering cost of a frame, see [`PrimitiveList::stats`] and
+/// [`WindowContext::stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+    pub vertices: usize,
+    pub indices: usize,
+    pub commands: usize,
+    /// Commands merged into others by batching
+    pub commands_saved: usize,
+    pub bind_groups_created: usize,
+    pub uploaded_bytes: u64,
+    /// Vertices left out as they did not fit into the staging buffer
+    pub truncated_vertices: usize,
+    /// Indices left out as they did not fit into the staging buffer
+    pub truncated_indices: usize,
+    /// Time spent in [`Context::on_frame`], apart from `gpu_wait`
+    pub cpu_time: Duration,
+    /// Time spent waiting for the GPU to finish with the staging buffer
+    pub gpu_wait: Duration,
+}
+
+impl FrameStats {
+    /// Draws the counters as text on a dark panel, at `pos` in pixels
+    pub fn draw_overlay(&self, primitives: &mut PrimitiveList, font: &Font, pos: [i32; 2]) {
+        let text = format!(
+            "vertices  {}\nindices   {}\ncommands  {} ({} saved)\nbind groups {}\n\
+             uploaded  {} KiB\ntruncated {} {}\ncpu time  {:.2} ms\ngpu wait  {:.2} ms",
+            self.vertices,
+            self.indices,
+            self.commands,
+            self.commands_saved,
+            self.bind_groups_created,
+            self.uploaded_bytes / 1024,
+            self.truncated_vertices,
+            self.truncated_indices,
+            self.cpu_time.as_secs_f64() * 1000.0,
+            self.gpu_wait.as_secs_f64() * 1000.0,
+        );
+        const PADDING: f32 = 4.0;
+        let [x, y] = pos.map(|it| it as f32);
+        let [width, height] = primitives.text_size(font, &text);
+        let rect = [
+            x - PADDING,
+            y - PADDING,
+            x + PADDING + width,
+            y + PADDING + height,
+        ];
+
+        let old_px_space = mem::replace(&mut primitives.px_space, true);
+        let old_texture = primitives.texture.take();
+        let old_color = primitives.color;
+        primitives.color = [0.0, 0.0, 0.0, 0.75];
+        primitives.rect_f(rect);
+        primitives.color = [1.0; 4];
+        primitives.text_i(font, pos, &text);
+        primitives.color = old_color;
+        primitives.texture = old_texture;
+        primitives.px_space = old_px_space;
+    }
+}
+
+/// An OS window together with its surface and the primitives drawn into it
+pub struct WindowContext {
+    pub primitives: PrimitiveList,
+    /// Counters of the last frame presented in the window
+    pub stats: FrameStats,
+    /// Offscreen copy of the window contents for [`Background::Load`], as
+    /// surface textures do not keep what was presented in them
+    pub canvas: Option<wgpu::Texture>,
+    /// Physical pixels per window coordinate, which mouse events are reported in
+    pub scale_factor: f32,
+    pub surface_config: wgpu::SurfaceConfiguration,
+    // Declared before the window, as it must be dropped first
+    pub surface: wgpu::Surface<'static>,
+    pub window: Window,
+}
+
 pub struct Context {
-    pub primitive_pipeline: wgpu::RenderPipeline,
+    pub pipeline_cache: Option<wgpu::PipelineCache>,
+    pub pipeline_cache_path: Option<PathBuf>,
+    pub primitive_pipelines: HashMap<wgt::TextureFormat, wgpu::RenderPipeline>,
     pub primitive_pipeline_layout: wgpu::PipelineLayout,
     pub primitive_pipeline_bind_group_layout: wgpu::BindGroupLayout,
+    pub primitive_shader: wgpu::ShaderModule,
     pub primitive_buffer: wgpu::Buffer,
     pub staging_buffers: [wgpu::Buffer; 2],
     pub submission_idx: [Option<wgpu::SubmissionIndex>; 2],
@@ -17,25 +130,132 @@ pub struct Context {
     pub white_tex: wgpu::Texture,
     pub default_sampler: wgpu::Sampler,
     pub current_frame: usize,
-    pub surface_config: wgpu::SurfaceConfiguration,
+    /// Textures shared by all windows, looked up by name
+    pub textures: HashMap<String, wgpu::Texture>,
+    /// Texture with a white texel, e.g. the font, that solid fills are moved
+    /// to when batching, so that they can share draw calls with it
+    pub batch_texture: Option<(wgpu::Texture, [u32; 2])>,
+    pub windows: HashMap<u32, WindowContext>,
+    pub main_window: u32,
+    pub config: ContextConfig,
     pub queue: wgpu::Queue,
     pub device: wgpu::Device,
-    pub surface: wgpu::Surface<'static>,
-    pub window: Window,
+    pub adapter: wgpu::Adapter,
+    pub instance: wgpu::Instance,
+    pub video: VideoSubsystem,
     pub event_pump: EventPump,
 }
 
+impl WindowContext {
+    fn new(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        window: Window,
+        surface: wgpu::Surface<'static>,
+        blend_space: BlendSpace,
+    ) -> Self {
+        let window_size = window.size_in_pixels();
+        let scale_factor = window.pixel_density();
+
+        let surface_caps = surface.get_capabilities(adapter);
+        let want_srgb = blend_space == BlendSpace::Linear;
+        let surface_format = surface_caps
+            .formats
+            .iter()
+            .find(|it| it.is_srgb() == want_srgb)
+            .copied()
+            .unwrap_or(surface_caps.formats[0]);
+        let surface_config = wgt::SurfaceConfiguration {
+            usage: wgt::TextureUsages::RENDER_ATTACHMENT
+                | (surface_caps.usages & wgt::TextureUsages::COPY_DST),
+            format: surface_format,
+            width: window_size.0.max(1),
+            height: window_size.1.max(1),
+            present_mode: wgt::PresentMode::AutoVsync,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: surface_caps.alpha_modes[0],
+            view_formats: Vec::new(),
+        };
+        surface.configure(device, &surface_config);
+
+        Self {
+            primitives: PrimitiveList::default(),
+            stats: FrameStats::default(),
+            canvas: None,
+            scale_factor,
+            surface_config,
+            surface,
+            window,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.window.id()
+    }
+
+    /// Clears the primitive list and sets it up for the current window size
+    pub fn begin_frame(&mut self) -> &mut PrimitiveList {
+        self.primitives.clear();
+        self.primitives.window_size = [self.surface_config.width, self.surface_config.height];
+        self.primitives.scale_factor = self.scale_factor;
+        &mut self.primitives
+    }
+
+    /// Makes sure the canvas matches the surface, preserving its contents
+    fn update_canvas(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let size = wgt::Extent3d {
+            width: self.surface_config.width,
+            height: self.surface_config.height,
+            depth_or_array_layers: 1,
+        };
+        if self.canvas.as_ref().is_some_and(|it| it.size() == size) {
+            return;
+        }
+        let canvas = device.create_texture(&wgt::TextureDescriptor {
+            label: Some("Canvas texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgt::TextureDimension::D2,
+            format: self.surface_config.format,
+            usage: wgt::TextureUsages::RENDER_ATTACHMENT
+                | wgt::TextureUsages::COPY_SRC
+                | wgt::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        if let Some(old_canvas) = self.canvas.take() {
+            let copy_size = wgt::Extent3d {
+                width: old_canvas.width().min(size.width),
+                height: old_canvas.height().min(size.height),
+                depth_or_array_layers: 1,
+            };
+            encoder.copy_texture_to_texture(
+                old_canvas.as_image_copy(),
+                canvas.as_image_copy(),
+                copy_size,
+            );
+        }
+        self.canvas = Some(canvas);
+    }
+
+    fn on_resize(&mut self, device: &wgpu::Device) {
+        // Moving to another display may change the density without a resize
+        self.scale_factor = self.window.pixel_density();
+        let (width, height) = self.window.size_in_pixels();
+        if width > 0 && height > 0 {
+            self.surface_config.width = width;
+            self.surface_config.height = height;
+            self.surface.configure(device, &self.surface_config);
+        }
+    }
+}
+
 impl Context {
-    pub async fn new() -> Self {
+    pub async fn new(config: ContextConfig) -> Self {
         let sdl = sdl3::init().unwrap();
-        let sdl_video = sdl.video().unwrap();
+        let video = sdl.video().unwrap();
         let event_pump = sdl.event_pump().unwrap();
-        let window = sdl_video
-            .window("Main window", 1280, 720)
-            .resizable()
-            .build()
-            .unwrap();
-        let wgpu_instance = wgpu::Instance::new(&wgt::InstanceDescriptor {
+        let instance = wgpu::Instance::new(&wgt::InstanceDescriptor {
             #[cfg(target_os = "windows")]
             backends: wgt::Backends::DX12,
             #[cfg(not(target_os = "windows"))]
@@ -47,12 +267,8 @@ impl Context {
             memory_budget_thresholds: Default::default(),
             backend_options: Default::default(),
         });
-        let window_size = window.size_in_pixels();
-        let surface = unsafe {
-            let target = wgpu::SurfaceTargetUnsafe::from_window(&window).unwrap();
-            wgpu_instance.create_surface_unsafe(target).unwrap()
-        };
-        let adapter = wgpu_instance
+        let (window, surface) = open_window(&instance, &video, "Main window", 1280, 720);
+        let adapter = instance
             .request_adapter(&wgt::RequestAdapterOptions {
                 power_preference: wgt::PowerPreference::HighPerformance,
                 force_fallback_adapter: false,
@@ -60,56 +276,27 @@ impl Context {
             })
             .await
             .unwrap();
-        let (device, queue) = adapter.request_device(&Default::default()).await.unwrap();
+        let mut required_features = wgt::Features::empty();
+        if config.pipeline_cache_dir.is_some() {
+            required_features |= adapter.features() & wgt::Features::PIPELINE_CACHE;
+        }
+        let device_desc = wgt::DeviceDescriptor {
+            required_features,
+            ..Default::default()
+        };
+        let (device, queue) = adapter.request_device(&device_desc).await.unwrap();
 
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps
-            .formats
-            .iter()
-            .find(|it| it.is_srgb())
-            .copied()
-            .unwrap_or(surface_caps.formats[0]);
-        let surface_config = wgt::SurfaceConfiguration {
-            usage: wgt::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: window_size.0.max(1),
-            height: window_size.1.max(1),
-            present_mode: wgt::PresentMode::AutoVsync,
-            desired_maximum_frame_latency: 2,
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: Vec::new(),
+        let (pipeline_cache, pipeline_cache_path) = match config.pipeline_cache_dir {
+            Some(ref dir) => load_pipeline_cache(&device, &adapter.get_info(), dir),
+            None => (None, None),
         };
-        surface.configure(&device, &surface_config);
 
-        let default_sampler = device.create_sampler(&Default::default());
+        let main_window =
+            WindowContext::new(&adapter, &device, window, surface, config.blend_space);
 
-        let white_tex_size = wgt::Extent3d {
-            width: 1,
-            height: 1,
-            depth_or_array_layers: 1,
-        };
-        let white_tex = device.create_texture(&wgt::TextureDescriptor {
-            label: Some("White texture"),
-            size: white_tex_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgt::TextureDimension::D2,
-            format: wgt::TextureFormat::Rgba8Unorm,
-            usage: wgt::TextureUsages::COPY_DST | wgt::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[wgt::TextureFormat::Rgba8Unorm],
-        });
+        let default_sampler = device.create_sampler(&Default::default());
+        let white_tex = create_white_texture(&device, &queue);
         let white_tex_view = white_tex.create_view(&Default::default());
-        queue.write_texture(
-            wgt::TexelCopyTextureInfo {
-                texture: &white_tex,
-                mip_level: 0,
-                origin: wgt::Origin3d::ZERO,
-                aspect: wgt::TextureAspect::All,
-            },
-            &[255; 4],
-            Default::default(),
-            white_tex_size,
-        );
 
         let staging_buffers = array::from_fn(|i| {
             device.create_buffer(&wgt::BufferDescriptor {
@@ -130,30 +317,9 @@ impl Context {
         });
 
         let shader_module_desc = wgpu::include_wgsl!("primitives.wgsl");
-        let shader_module = device.create_shader_module(shader_module_desc);
+        let primitive_shader = device.create_shader_module(shader_module_desc);
 
-        let primitive_pipeline_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Primitive pipeline bind group layout"),
-                entries: &[
-                    wgt::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgt::ShaderStages::FRAGMENT,
-                        ty: wgt::BindingType::Texture {
-                            sample_type: wgt::TextureSampleType::Float { filterable: false },
-                            view_dimension: wgt::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgt::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgt::ShaderStages::FRAGMENT,
-                        ty: wgt::BindingType::Sampler(wgt::SamplerBindingType::NonFiltering),
-                        count: None,
-                    },
-                ],
-            });
+        let primitive_pipeline_bind_group_layout = create_primitive_bind_group_layout(&device);
 
         let primitive_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -162,59 +328,14 @@ impl Context {
                 push_constant_ranges: &[],
             });
 
-        let primitive_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Primitive pipeline"),
-            layout: Some(&primitive_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: None,
-                compilation_options: Default::default(),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: mem::size_of::<Vertex>() as u64,
-                    step_mode: wgt::VertexStepMode::Vertex,
-                    attributes: &[
-                        wgt::VertexAttribute {
-                            format: wgt::VertexFormat::Float32x2,
-                            offset: mem::offset_of!(Vertex, coord) as u64,
-                            shader_location: 0,
-                        },
-                        wgt::VertexAttribute {
-                            format: wgt::VertexFormat::Float32x4,
-                            offset: mem::offset_of!(Vertex, color) as u64,
-                            shader_location: 1,
-                        },
-                        wgt::VertexAttribute {
-                            format: wgt::VertexFormat::Float32x2,
-                            offset: mem::offset_of!(Vertex, tex_coord) as u64,
-                            shader_location: 2,
-                        },
-                    ],
-                }],
-            },
-            primitive: wgt::PrimitiveState {
-                cull_mode: Some(wgt::Face::Back),
-                ..Default::default()
-            },
-            depth_stencil: None,
-            multisample: Default::default(),
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: None,
-                compilation_options: Default::default(),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: Some(wgt::BlendState::ALPHA_BLENDING),
-                    write_mask: wgt::ColorWrites::ALL,
-                })],
-            }),
-            multiview: None,
-            cache: None,
-        });
-
-        Self {
-            primitive_pipeline,
+        let main_window_id = main_window.id();
+        let mut ctx = Self {
+            pipeline_cache,
+            pipeline_cache_path,
+            primitive_pipelines: HashMap::new(),
             primitive_pipeline_layout,
             primitive_pipeline_bind_group_layout,
+            primitive_shader,
             primitive_buffer,
             staging_buffers,
             submission_idx: array::from_fn(|_| None),
@@ -222,34 +343,146 @@ impl Context {
             white_tex,
             default_sampler,
             current_frame: 0,
-            surface_config,
+            textures: HashMap::new(),
+            batch_texture: None,
+            windows: HashMap::new(),
+            main_window: main_window_id,
+            config,
             queue,
             device,
-            surface,
-            window,
+            adapter,
+            instance,
+            video,
             event_pump,
+        };
+        ctx.add_window(main_window);
+        ctx
+    }
+
+    /// Opens another OS window that shares the device, pipelines and textures
+    pub fn create_window(&mut self, title: &str, width: u32, height: u32) -> u32 {
+        let (window, surface) = open_window(&self.instance, &self.video, title, width, height);
+        let blend_space = self.config.blend_space;
+        let window = WindowContext::new(&self.adapter, &self.device, window, surface, blend_space);
+        self.add_window(window)
+    }
+
+    fn add_window(&mut self, window: WindowContext) -> u32 {
+        let format = window.surface_config.format;
+        if !self.primitive_pipelines.contains_key(&format) {
+            let pipeline = create_primitive_pipeline(
+                &self.device,
+                &self.primitive_pipeline_layout,
+                &self.primitive_shader,
+                self.pipeline_cache.as_ref(),
+                format,
+            );
+            self.primitive_pipelines.insert(format, pipeline);
+            self.save_pipeline_cache();
         }
+        let id = window.id();
+        self.windows.insert(id, window);
+        id
     }
 
-    pub fn on_resize(&mut self) {
-        let (width, height) = self.window.size_in_pixels();
-        if width > 0 && height > 0 {
-            self.surface_config.width = width;
-            self.surface_config.height = height;
-            self.surface.configure(&self.device, &self.surface_config);
+    /// Writes the pipeline cache to disk, failures only cost startup time later
+    pub fn save_pipeline_cache(&self) {
+        let (Some(cache), Some(path)) = (&self.pipeline_cache, &self.pipeline_cache_path) else {
+            return;
+        };
+        let Some(data) = cache.get_data() else {
+            return;
+        };
+        // Write to a temporary file first so that readers never see a partial cache
+        let temp_path = path.with_extension("temp");
+        let _ = fs::write(&temp_path, &data).and_then(|()| fs::rename(&temp_path, path));
+    }
+
+    pub fn close_window(&mut self, window_id: u32) {
+        self.windows.remove(&window_id);
+    }
+
+    /// Writes what was last drawn into a window to a file, see [`capture`]
+    pub fn save_primitives(&self, window_id: u32, path: &Path) -> io::Result<()> {
+        let Some(window) = self.windows.get(&window_id) else {
+            return Err(io::ErrorKind::NotFound.into());
+        };
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        capture::write_primitives(&mut file, &window.primitives, &self.textures)?;
+        file.flush()
+    }
+
+    /// Replaces the primitives of a window with a saved capture, which is
+    /// then drawn by `on_frame` until the next `begin_frame`
+    pub fn load_primitives(&mut self, window_id: u32, path: &Path) -> io::Result<()> {
+        let Some(window) = self.windows.get_mut(&window_id) else {
+            return Err(io::ErrorKind::NotFound.into());
+        };
+        let mut file = BufReader::new(fs::File::open(path)?);
+        capture::read_primitives(&mut file, &mut window.primitives, &self.textures)
+    }
+
+    pub fn main_window(&self) -> &WindowContext {
+        &self.windows[&self.main_window]
+    }
+
+    pub fn main_window_mut(&mut self) -> &mut WindowContext {
+        self.windows.get_mut(&self.main_window).unwrap()
+    }
+
+    pub fn on_resize(&mut self, window_id: u32) {
+        if let Some(window) = self.windows.get_mut(&window_id) {
+            window.on_resize(&self.device);
         }
     }
 
-    pub fn on_frame(&mut self, primitives: &PrimitiveList) -> Result<(), wgpu::SurfaceError> {
-        let out_tex = self.surface.get_current_texture()?;
+    pub fn on_frame(&mut self, window_id: u32) -> Result<(), wgpu::SurfaceError> {
+        let start_time = Instant::now();
+        let Some(window) = self.windows.get_mut(&window_id) else {
+            return Ok(());
+        };
+        let out_tex = window.surface.get_current_texture()?;
         let out_tex_view = out_tex.texture.create_view(&Default::default());
         let mut encoder = self.device.create_command_encoder(&Default::default());
+        // Without copies into the surface we can only hope the swapchain keeps it
+        let use_canvas = window.primitives.background == Background::Load
+            && window
+                .surface_config
+                .usage
+                .contains(wgt::TextureUsages::COPY_DST);
+        if use_canvas {
+            window.update_canvas(&self.device, &mut encoder);
+        } else {
+            window.canvas = None;
+        }
+        let shared = self
+            .batch_texture
+            .as_ref()
+            .map(|(tex, texel)| (tex, *texel));
+        let batch_stats = window.primitives.batch(shared);
+        let mut stats = window.primitives.stats();
+        stats.commands_saved = batch_stats.saved();
+        let window = &*window;
+        let primitives = &window.primitives;
+        let canvas_view = window
+            .canvas
+            .as_ref()
+            .map(|it| it.create_view(&Default::default()));
+        let target_view = canvas_view.as_ref().unwrap_or(&out_tex_view);
+        let load = match primitives.background {
+            Background::Clear(color) => {
+                wgt::LoadOp::Clear(clear_color(color, window.surface_config.format))
+            }
+            Background::Load => wgt::LoadOp::Load,
+        };
         if let Some(idx) = mem::take(&mut self.submission_idx[self.current_frame]) {
             let poll_type = wgpu::PollType::Wait {
                 submission_index: Some(idx),
                 timeout: None,
             };
+            let wait_time = Instant::now();
             self.device.poll(poll_type).unwrap();
+            stats.gpu_wait = wait_time.elapsed();
         }
         let staging = &self.staging_buffers[self.current_frame];
         let mut mapping = staging.get_mapped_range_mut(..);
@@ -281,6 +514,9 @@ impl Context {
         let off_vtx = off_vtx as u64;
         let off_idx = off_idx as u64;
         let off_end = off_end as u64;
+        stats.uploaded_bytes = off_end;
+        stats.truncated_vertices = primitives.vtx.len() - count_vtx;
+        stats.truncated_indices = primitives.idx.len() - count_idx;
         mem::drop(mapping);
         let staging = &self.staging_buffers[self.current_frame];
         encoder.copy_buffer_to_buffer(staging, 0, &self.primitive_buffer, 0, Some(off_end));
@@ -289,11 +525,11 @@ impl Context {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Primitive render pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &out_tex_view,
+                view: target_view,
                 depth_slice: None,
                 resolve_target: None,
                 ops: wgt::Operations {
-                    load: wgt::LoadOp::Clear(wgt::Color::BLUE),
+                    load,
                     store: wgt::StoreOp::Store,
                 },
             })],
@@ -301,7 +537,7 @@ impl Context {
         });
         let buf_slice_vtx = self.primitive_buffer.slice(off_vtx..off_idx);
         let buf_slice_idx = self.primitive_buffer.slice(off_idx..off_end);
-        render_pass.set_pipeline(&self.primitive_pipeline);
+        render_pass.set_pipeline(&self.primitive_pipelines[&window.surface_config.format]);
         render_pass.set_index_buffer(buf_slice_idx, wgpu::IndexFormat::Uint32);
         render_pass.set_vertex_buffer(0, buf_slice_vtx);
         for cmd in &primitives.commands {
@@ -323,13 +559,25 @@ impl Context {
                     },
                 ],
             });
+            stats.bind_groups_created += 1;
             render_pass.set_bind_group(0, &bind_group, &[]);
+            let size = [window.surface_config.width, window.surface_config.height];
+            let [x, y, width, height] = scissor_rect(cmd.clip, size);
+            render_pass.set_scissor_rect(x, y, width, height);
             let cmd_idx_off = count_idx.min(cmd.idx_off) as u32;
             let cmd_idx_cnt = count_idx.min(cmd.idx_off + cmd.idx_cnt) as u32;
             render_pass.draw_indexed(cmd_idx_off..cmd_idx_cnt, 0, 0..1);
         }
         mem::drop(render_pass);
 
+        if let Some(ref canvas) = window.canvas {
+            encoder.copy_texture_to_texture(
+                canvas.as_image_copy(),
+                out_tex.texture.as_image_copy(),
+                canvas.size(),
+            );
+        }
+
         let staging = &self.staging_buffers[self.current_frame];
         encoder.map_buffer_on_submit(staging, wgpu::MapMode::Write, .., Result::unwrap);
 
@@ -337,10 +585,235 @@ impl Context {
         self.submission_idx[self.current_frame] = Some(submission_idx);
         self.current_frame ^= 1;
         out_tex.present();
+        stats.cpu_time = start_time.elapsed().saturating_sub(stats.gpu_wait);
+        if let Some(window) = self.windows.get_mut(&window_id) {
+            window.stats = stats;
+        }
         Ok(())
     }
 }
 
+fn open_window(
+    instance: &wgpu::Instance,
+    video: &VideoSubsystem,
+    title: &str,
+    width: u32,
+    height: u32,
+) -> (Window, wgpu::Surface<'static>) {
+    let window = video
+        .window(title, width, height)
+        .resizable()
+        .high_pixel_density()
+        .build()
+        .unwrap();
+    // The surface is stored next to the window and dropped before it
+    let surface = unsafe {
+        let target = wgpu::SurfaceTargetUnsafe::from_window(&window).unwrap();
+        instance.create_surface_unsafe(target).unwrap()
+    };
+    (window, surface)
+}
+
+/// 1x1 texture bound for commands without a texture
+pub fn create_white_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Texture {
+    let size = wgt::Extent3d {
+        width: 1,
+        height: 1,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgt::TextureDescriptor {
+        label: Some("White texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgt::TextureDimension::D2,
+        format: wgt::TextureFormat::Rgba8Unorm,
+        usage: wgt::TextureUsages::COPY_DST | wgt::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[wgt::TextureFormat::Rgba8Unorm],
+    });
+    queue.write_texture(
+        wgt::TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgt::Origin3d::ZERO,
+            aspect: wgt::TextureAspect::All,
+        },
+        &[255; 4],
+        Default::default(),
+        size,
+    );
+    texture
+}
+
+pub fn create_primitive_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Primitive pipeline bind group layout"),
+        entries: &[
+            wgt::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgt::ShaderStages::FRAGMENT,
+                ty: wgt::BindingType::Texture {
+                    sample_type: wgt::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgt::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgt::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgt::ShaderStages::FRAGMENT,
+                ty: wgt::BindingType::Sampler(wgt::SamplerBindingType::NonFiltering),
+                count: None,
+            },
+        ],
+    })
+}
+
+pub fn create_primitive_pipeline(
+    device: &wgpu::Device,
+    primitive_pipeline_layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    cache: Option<&wgpu::PipelineCache>,
+    surface_format: wgt::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Primitive pipeline"),
+        layout: Some(primitive_pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: None,
+            compilation_options: Default::default(),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: mem::size_of::<Vertex>() as u64,
+                step_mode: wgt::VertexStepMode::Vertex,
+                attributes: &[
+                    wgt::VertexAttribute {
+                        format: wgt::VertexFormat::Float32x2,
+                        offset: mem::offset_of!(Vertex, coord) as u64,
+                        shader_location: 0,
+                    },
+                    wgt::VertexAttribute {
+                        format: wgt::VertexFormat::Float32x4,
+                        offset: mem::offset_of!(Vertex, color) as u64,
+                        shader_location: 1,
+                    },
+                    wgt::VertexAttribute {
+                        format: wgt::VertexFormat::Float32x2,
+                        offset: mem::offset_of!(Vertex, tex_coord) as u64,
+                        shader_location: 2,
+                    },
+                ],
+            }],
+        },
+        // Tessellated shapes do not keep a consistent winding
+        primitive: wgt::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: Default::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: None,
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &[("LINEAR_BLENDING", linear_blending_constant(surface_format))],
+                ..Default::default()
+            },
+            targets: &[Some(wgpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(wgt::BlendState::ALPHA_BLENDING),
+                write_mask: wgt::ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+        cache,
+    })
+}
+
+fn load_pipeline_cache(
+    device: &wgpu::Device,
+    adapter_info: &wgt::AdapterInfo,
+    dir: &Path,
+) -> (Option<wgpu::PipelineCache>, Option<PathBuf>) {
+    if !device.features().contains(wgt::Features::PIPELINE_CACHE) {
+        return (None, None);
+    }
+    let Some(key) = wgpu::util::pipeline_cache_key(adapter_info) else {
+        return (None, None);
+    };
+    if fs::create_dir_all(dir).is_err() {
+        return (None, None);
+    }
+    // The key only covers the device, different drivers get separate files
+    let driver_hash = fnv1a([
+        adapter_info.name.as_bytes(),
+        adapter_info.driver.as_bytes(),
+        adapter_info.driver_info.as_bytes(),
+    ]);
+    let path = dir.join(format!("{key}_{driver_hash:016x}"));
+    let data = fs::read(&path).ok();
+    // SAFETY: the data was produced by `PipelineCache::get_data` unless someone
+    // tampered with the cache directory. With `fallback` set, data that does
+    // not match the adapter or driver is discarded instead of failing.
+    let cache = unsafe {
+        device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+            label: Some("Pipeline cache"),
+            data: data.as_deref(),
+            fallback: true,
+        })
+    };
+    (Some(cache), Some(path))
+}
+
+/// Stable across Rust versions, unlike `DefaultHasher`
+fn fnv1a<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+    let mut hash = 0xcbf29ce484222325_u64;
+    for part in parts {
+        for &b in part.iter().chain([&0]) {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// Clear values are written as is, so sRGB targets need them decoded
+pub fn clear_color([r, g, b, a]: [f32; 4], format: wgt::TextureFormat) -> wgt::Color {
+    let [r, g, b] = if format.is_srgb() {
+        [r, g, b].map(srgb_to_linear)
+    } else {
+        [r, g, b]
+    };
+    let [r, g, b, a] = [r, g, b, a].map(f64::from);
+    wgt::Color { r, g, b, a }
+}
+
+/// Clip rect of a command as x, y, width and height within the target
+pub fn scissor_rect(clip: Option<[u32; 4]>, [width, height]: [u32; 2]) -> [u32; 4] {
+    let [x1, y1, x2, y2] = clip.unwrap_or([0, 0, width, height]);
+    let [x1, x2] = [x1.min(width), x2.min(width)];
+    let [y1, y2] = [y1.min(height), y2.min(height)];
+    [x1, y1, x2.saturating_sub(x1), y2.saturating_sub(y1)]
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Blending happens in linear space exactly when the target is sRGB
+fn linear_blending_constant(format: wgt::TextureFormat) -> f64 {
+    if format.is_srgb() { 1.0 } else { 0.0 }
+}
+
 fn calc_count<T>(curr_off: usize, arr: &[T]) -> (usize, usize) {
     let size = mem::size_of::<T>();
     let remaining = STAGING_BUFFER_SIZE as usize - curr_off;
@@ -0,0 +1,353 @@
+use crate::{
+    capture::{Capture, CapturedTexture},
+    primitives::{Background, Vertex},
+    program::{self, BlendSpace},
+};
+use std::collections::HashMap;
+
+/// Vertex positions are snapped to this fraction of a pixel, as on GPUs
+const SUBPIXEL_STEPS: f64 = 256.0;
+
+/// Texels of a texture on the CPU, as RGBA rows top to bottom
+#[allow(unused)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    pub size: [u32; 2],
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Nearest texel, clamped to the edge like the default sampler
+    fn sample(&self, [u, v]: [f32; 2]) -> [f32; 4] {
+        let [width, height] = self.size;
+        if width == 0 || height == 0 {
+            return [0.0; 4];
+        }
+        let x = ((u * width as f32).floor() as i64).clamp(0, width as i64 - 1) as usize;
+        let y = ((v * height as f32).floor() as i64).clamp(0, height as i64 - 1) as usize;
+        let off = (x + width as usize * y) * 4;
+        [0, 1, 2, 3].map(|i| self.data[off + i] as f32 / 255.0)
+    }
+}
+
+/// Renders a capture on the CPU the way `primitives.wgsl` does, into RGBA
+/// rows like [`crate::offscreen::Offscreen::render`]. Serves as a reference
+/// in tests and works without any device. Named textures sample the image
+/// of that name in `images`, commands with others are left out like on
+/// replay.
+#[allow(unused)]
+pub fn rasterize(
+    primitives: &Capture,
+    blend_space: BlendSpace,
+    images: &HashMap<String, Image>,
+) -> Vec<u8> {
+    let size = primitives.window_size.map(|it| it.max(1));
+    let linear = blend_space == BlendSpace::Linear;
+    let clear = match primitives.background {
+        Background::Clear(color) => color.map(unorm),
+        Background::Load => [0; 4],
+    };
+    let mut pixels = clear.repeat((size[0] * size[1]) as usize);
+
+    for cmd in &primitives.commands {
+        let texture = match cmd.texture {
+            CapturedTexture::None => None,
+            CapturedTexture::Named(ref name) => match images.get(name) {
+                Some(image) => Some(image),
+                None => continue,
+            },
+            CapturedTexture::Unnamed => continue,
+        };
+        let scissor = program::scissor_rect(cmd.clip, size);
+        let idx_end = (cmd.idx_off + cmd.idx_cnt).min(primitives.idx.len());
+        let idx = &primitives.idx[cmd.idx_off.min(idx_end)..idx_end];
+        for tri in idx.chunks_exact(3) {
+            let [Some(&v0), Some(&v1), Some(&v2)] =
+                [0, 1, 2].map(|i| primitives.vtx.get(tri[i] as usize))
+            else {
+                continue;
+            };
+            let target = Target {
+                pixels: &mut pixels,
+                size,
+                scissor,
+                linear,
+            };
+            fill_triangle(target, [v0, v1, v2], texture);
+        }
+    }
+    pixels
+}
+
+struct Target<'a> {
+    pixels: &'a mut [u8],
+    size: [u32; 2],
+    scissor: [u32; 4],
+    linear: bool,
+}
+
+/// Covers the pixels whose centers are inside, with the top-left rule for
+/// those on an edge, so that triangles sharing an edge do not overlap
+fn fill_triangle(target: Target, mut tri: [Vertex; 3], texture: Option<&Image>) {
+    let [width, height] = target.size.map(f64::from);
+    let snap = |x: f64| (x * SUBPIXEL_STEPS).round() / SUBPIXEL_STEPS;
+    let to_px = |v: &Vertex| {
+        let [x, y] = v.coord.map(f64::from);
+        [
+            snap((x + 1.0) * 0.5 * width),
+            snap((1.0 - y) * 0.5 * height),
+        ]
+    };
+    let mut pts = tri.each_ref().map(to_px);
+    if pts.iter().flatten().any(|it| !it.is_finite()) {
+        return;
+    }
+    let mut area = edge(pts[0], pts[1], pts[2]);
+    if area == 0.0 {
+        return;
+    }
+    // Culling is disabled, so both windings are drawn
+    if area < 0.0 {
+        tri.swap(1, 2);
+        pts.swap(1, 2);
+        area = -area;
+    }
+
+    let [sx, sy, sw, sh] = target.scissor;
+    let min = [0, 1].map(|i| pts.iter().map(|p| p[i]).fold(f64::INFINITY, f64::min));
+    let max = [0, 1].map(|i| pts.iter().map(|p| p[i]).fold(-f64::INFINITY, f64::max));
+    let x1 = (min[0].floor().max(0.0) as u32).max(sx);
+    let y1 = (min[1].floor().max(0.0) as u32).max(sy);
+    let x2 = (max[0].ceil().max(0.0) as u32).min(sx + sw);
+    let y2 = (max[1].ceil().max(0.0) as u32).min(sy + sh);
+
+    let edges = [(1, 2), (2, 0), (0, 1)];
+    for y in y1..y2 {
+        for x in x1..x2 {
+            let p = [x as f64 + 0.5, y as f64 + 0.5];
+            let mut weights = [0.0; 3];
+            let mut inside = true;
+            for (k, &(i, j)) in edges.iter().enumerate() {
+                let e = edge(pts[i], pts[j], p);
+                inside &= e > 0.0 || (e == 0.0 && is_top_left(pts[i], pts[j]));
+                weights[k] = (e / area) as f32;
+            }
+            if !inside {
+                continue;
+            }
+            let interpolate = |attr: &dyn Fn(&Vertex) -> f32| {
+                (0..3).map(|k| weights[k] * attr(&tri[k])).sum::<f32>()
+            };
+            let tint = [0, 1, 2, 3].map(|i| interpolate(&|v| v.color[i]));
+            let tex_coord = [0, 1].map(|i| interpolate(&|v| v.tex_coord[i]));
+            let sampled = texture.map_or([1.0; 4], |it| it.sample(tex_coord));
+            let mut color = [0, 1, 2, 3].map(|i| (tint[i] * sampled[i]).clamp(0.0, 1.0));
+            if target.linear {
+                for c in &mut color[..3] {
+                    *c = program::srgb_to_linear(*c);
+                }
+            }
+            let off = (x + target.size[0] * y) as usize * 4;
+            blend(&mut target.pixels[off..][..4], color, target.linear);
+        }
+    }
+}
+
+/// Twice the signed area of `a, b, p`, positive when `p` is to the right of
+/// `a -> b` with y pointing down
+fn edge(a: [f64; 2], b: [f64; 2], p: [f64; 2]) -> f64 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+fn is_top_left(a: [f64; 2], b: [f64; 2]) -> bool {
+    let [dx, dy] = [b[0] - a[0], b[1] - a[1]];
+    (dy == 0.0 && dx > 0.0) || dy < 0.0
+}
+
+/// `BlendState::ALPHA_BLENDING`, with sRGB targets decoding and encoding the
+/// stored color around it
+fn blend(dst: &mut [u8], src: [f32; 4], linear: bool) {
+    let mut d = [0, 1, 2, 3].map(|i| dst[i] as f32 / 255.0);
+    if linear {
+        for c in &mut d[..3] {
+            *c = program::srgb_to_linear(*c);
+        }
+    }
+    let a = src[3];
+    let mut out = [0, 1, 2].map(|i| src[i] * a + d[i] * (1.0 - a));
+    if linear {
+        out = out.map(program::linear_to_srgb);
+    }
+    let [r, g, b] = out.map(unorm);
+    dst.copy_from_slice(&[r, g, b, unorm(a + d[3] * (1.0 - a))]);
+}
+
+fn unorm(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        golden,
+        gradient::Gradient,
+        offscreen::Offscreen,
+        primitives::{ImageOptions, PrimitiveList, TexRect, Transform},
+        testing,
+    };
+
+    fn list(size: [u32; 2]) -> PrimitiveList {
+        PrimitiveList {
+            window_size: size,
+            background: Background::Clear([0.0, 0.0, 0.0, 1.0]),
+            ..Default::default()
+        }
+    }
+
+    fn untextured(list: &PrimitiveList, blend_space: BlendSpace) -> Vec<u8> {
+        let capture = Capture::new(list, &HashMap::new());
+        rasterize(&capture, blend_space, &HashMap::new())
+    }
+
+    #[test]
+    fn shared_edges_cover_once() {
+        let mut list = list([16, 16]);
+        list.px_space = true;
+        list.color = [1.0, 1.0, 1.0, 0.5];
+        list.transform = Transform::rotate(0.3);
+        list.rect_f([4.0, 4.0, 12.0, 12.0]);
+        let pixels = untextured(&list, BlendSpace::Srgb);
+        for px in pixels.chunks_exact(4) {
+            assert!(matches!(px[0], 0 | 127 | 128), "{px:?}");
+        }
+    }
+
+    #[test]
+    fn pixel_aligned_rect() {
+        let mut list = list([8, 8]);
+        list.px_space = true;
+        list.color = [1.0; 4];
+        list.rect_f([2.0, 3.0, 5.0, 7.0]);
+        let pixels = untextured(&list, BlendSpace::Srgb);
+        for (i, px) in pixels.chunks_exact(4).enumerate() {
+            let [x, y] = [i % 8, i / 8];
+            let inside = (2..5).contains(&x) && (3..7).contains(&y);
+            assert_eq!(px[0] == 255, inside, "{x} {y}");
+        }
+    }
+
+    #[test]
+    fn blend_spaces() {
+        let mut list = list([1, 1]);
+        list.color = [1.0, 1.0, 1.0, 0.5];
+        list.rect_f([-1.0, -1.0, 1.0, 1.0]);
+        assert_eq!(untextured(&list, BlendSpace::Srgb), [128, 128, 128, 255]);
+        assert_eq!(untextured(&list, BlendSpace::Linear), [188, 188, 188, 255]);
+    }
+
+    #[test]
+    fn nearest_sampling() {
+        let image = Image {
+            size: [2, 1],
+            data: vec![10, 20, 30, 40, 50, 60, 70, 80],
+        };
+        let texel = |u| image.sample([u, 0.5]).map(|c| (c * 255.0).round() as u8);
+        assert_eq!(texel(-1.0), [10, 20, 30, 40]);
+        assert_eq!(texel(0.49), [10, 20, 30, 40]);
+        assert_eq!(texel(0.5), [50, 60, 70, 80]);
+        assert_eq!(texel(2.0), [50, 60, 70, 80]);
+    }
+
+    #[test]
+    fn textured_by_name() {
+        let image = Image {
+            size: [2, 2],
+            data: vec![
+                255, 0, 0, 255, 0, 255, 0, 255, //
+                0, 0, 255, 255, 255, 255, 255, 255,
+            ],
+        };
+        let texture = testing::texture(image.size);
+        let mut list = list([4, 4]);
+        list.px_space = true;
+        list.color = [1.0; 4];
+        list.texture = Some(texture.clone());
+        let src = TexRect::Uv([0.0, 0.0, 1.0, 1.0]);
+        list.image_rect_f([0.0, 0.0, 4.0, 4.0], src, &Default::default());
+        let textures = HashMap::from([("checker".to_string(), texture)]);
+        let capture = Capture::new(&list, &textures);
+        let checker = CapturedTexture::Named("checker".to_string());
+        assert_eq!(capture.commands[0].texture, checker);
+
+        let images = HashMap::from([("checker".to_string(), image)]);
+        let pixels = rasterize(&capture, BlendSpace::Srgb, &images);
+        // Every texel covers two by two pixels
+        let texels = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255; 4],
+        ];
+        for (i, px) in pixels.chunks_exact(4).enumerate() {
+            let [x, y] = [i % 4 / 2, i / 4 / 2];
+            assert_eq!(px, texels[x + 2 * y], "pixel {i}");
+        }
+
+        // Without an image of that name, it is left out
+        let pixels = rasterize(&capture, BlendSpace::Srgb, &HashMap::new());
+        assert!(pixels.chunks_exact(4).all(|px| px == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn matches_gpu() {
+        let image = Image {
+            size: [4, 4],
+            data: (0..64).map(|i| (i * 37 % 256) as u8).collect(),
+        };
+        for blend_space in [BlendSpace::Srgb, BlendSpace::Linear] {
+            let Some(offscreen) = pollster::block_on(Offscreen::new(blend_space)) else {
+                eprintln!("no adapter available, skipping comparison with the GPU");
+                return;
+            };
+            let texture = offscreen.create_texture(&image);
+
+            let mut list = list([96, 96]);
+            list.px_space = true;
+            list.feather = true;
+            list.color = [1.0, 0.5, 0.25, 0.75];
+            list.circle_f([30.0, 30.0], 20.5);
+            list.gradient = Some(Gradient::linear(
+                [40.0, 0.0],
+                [90.0, 0.0],
+                &[(0.0, [1.0, 0.0, 0.0, 1.0]), (1.0, [0.0, 0.0, 1.0, 0.5])],
+            ));
+            list.color = [1.0; 4];
+            list.rect_f([40.0, 10.0, 90.0, 50.0]);
+            list.gradient = None;
+            list.stroke.width = 3.0;
+            list.color = [0.0, 1.0, 0.5, 0.6];
+            list.polyline_f(&[[5.0, 90.0], [50.0, 55.0], [90.0, 85.0]], false);
+            list.clip_rect = Some([0, 60, 70, 96]);
+            list.texture = Some(texture.clone());
+            let options = ImageOptions {
+                rotation: 0.2,
+                ..Default::default()
+            };
+            let src = TexRect::Uv([0.0, 0.0, 1.0, 1.0]);
+            list.image_rect_f([50.0, 50.0, 82.0, 82.0], src, &options);
+
+            let expected = offscreen.render(&list);
+            let textures = HashMap::from([("image".to_string(), texture)]);
+            let images = HashMap::from([("image".to_string(), image.clone())]);
+            let capture = Capture::new(&list, &textures);
+            let actual = rasterize(&capture, blend_space, &images);
+            let cmp = golden::compare_images(&expected, &actual, golden::DEFAULT_TOLERANCE);
+            assert_eq!(
+                cmp.mismatched, 0,
+                "{blend_space:?}, max diff {}",
+                cmp.max_diff
+            );
+        }
+    }
+}
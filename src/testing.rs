@@ -0,0 +1,22 @@
+use std::sync::OnceLock;
+
+/// A texture for tests that record but do not render, from a stub device
+/// that works without any adapter
+pub fn texture([width, height]: [u32; 2]) -> wgpu::Texture {
+    static DEVICE: OnceLock<wgpu::Device> = OnceLock::new();
+    let device = DEVICE.get_or_init(|| wgpu::Device::noop(&Default::default()).0);
+    device.create_texture(&wgpu::wgt::TextureDescriptor {
+        label: Some("Test texture"),
+        size: wgpu::wgt::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::wgt::TextureDimension::D2,
+        format: wgpu::wgt::TextureFormat::Rgba8Unorm,
+        usage: wgpu::wgt::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}